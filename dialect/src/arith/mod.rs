@@ -1,15 +1,39 @@
-use lorax::{Operation, Value, def_op};
+use lorax::attr::TypedInt;
+use lorax::dialect::Dialect;
+use lorax::{Operation, RewriteRuleSet, RewritingCtx, Value, def_op, rewrite_pattern};
 
 def_op! {
-    arith.negate(val: Value)
+    arith.negate(val: Value) where val: Int, result: SameAs(val) [Pure]
 }
 
 def_op! {
-    arith.complement(val: Value)
+    arith.complement(val: Value) where val: Int, result: SameAs(val) [Pure]
 }
 
 def_op! {
     arith.constant() {
-        value: u32
-    }
+        value: TypedInt
+    } where result: SameAs(value) [Pure]
+}
+
+rewrite_pattern! {
+    /// -(-x) is x
+    pub struct DoubleNegate: arith.negate(arith.negate(x)) => { x }
+
+    /// ~(~x) is x
+    pub struct DoubleComplement: arith.complement(arith.complement(x)) => { x }
+}
+
+/// Rules that put arith ops in a simpler form, without changing what they compute
+pub fn canonicalization<'ctx>() -> RewriteRuleSet<RewritingCtx<'ctx>> {
+    RewriteRuleSet::new()
+        .add_rule(DoubleNegate)
+        .add_rule(DoubleComplement)
+}
+
+pub fn dialect() -> Dialect {
+    Dialect::new("arith")
+        .op(&negate::DEFINITION)
+        .op(&complement::DEFINITION)
+        .op(&constant::DEFINITION)
 }
@@ -1,9 +1,22 @@
+use lorax::attr::SymbolRef;
+use lorax::dialect::Dialect;
 use lorax::{Block, Operation, Value, def_op};
 
 def_op! {
-    func.func(block: Block)
+    func.func(block: Block) { sym_name: String } [IsolatedFromAbove]
 }
 
 def_op! {
-    func.ret(val: Value) -> None
+    func.call() { callee: SymbolRef }
+}
+
+def_op! {
+    func.ret(val: Value) -> None where val: Int [Terminator]
+}
+
+pub fn dialect() -> Dialect {
+    Dialect::new("func")
+        .op(&func::DEFINITION)
+        .op(&call::DEFINITION)
+        .op(&ret::DEFINITION)
 }
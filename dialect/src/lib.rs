@@ -1,4 +1,118 @@
+use lorax::dialect::Dialect;
+use lorax::{Block, GreedyConfig, Pass, PassRegistry, Verifier, rewrite_greedily};
+
 pub mod arith;
 pub mod func;
 
 pub mod x86;
+
+pub fn dialects() -> [Dialect; 3] {
+    [arith::dialect(), func::dialect(), x86::dialect()]
+}
+
+/// Make the ops of every dialect in this crate known to lorax
+pub fn register() {
+    dialects().iter().for_each(Dialect::register);
+}
+
+/// A verifier that knows the definitions of every dialect in this crate.
+pub fn verifier() -> Verifier {
+    dialects()
+        .iter()
+        .fold(Verifier::new(), |verifier, dialect| {
+            verifier.dialect(dialect)
+        })
+}
+
+/// Simplify ops with the canonicalization rules of every dialect
+pub struct Canonicalize;
+
+impl Pass for Canonicalize {
+    fn name(&self) -> &'static str {
+        "canonicalize"
+    }
+
+    fn run(&self, block: &mut Block) -> Result<(), String> {
+        rewrite_greedily(block, arith::canonicalization(), GreedyConfig::default());
+        Ok(())
+    }
+}
+
+/// Every pass in this crate, by the name pipelines use
+pub fn passes() -> PassRegistry {
+    PassRegistry::new()
+        .pass("canonicalize", || Box::new(Canonicalize))
+        .pass("x86-lower", || Box::new(x86::Lower))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lorax::attr::TypedInt;
+    use lorax::dialect::OpTrait;
+    use lorax::verify::{Diagnostic, DiagnosticKind};
+    use lorax::{PassManager, Type, Value};
+
+    #[test]
+    fn registered_ops_have_traits() {
+        register();
+
+        assert!(arith::constant(TypedInt(1, Type::int(32, true))).has_trait(OpTrait::Pure));
+        assert!(func::ret(Value::new(None)).has_trait(OpTrait::Terminator));
+        assert!(func::func(Block::new(), "main".to_owned()).has_trait(OpTrait::IsolatedFromAbove));
+        assert_eq!(
+            lorax::dialect::lookup("x86.mov").map(|def| def.operands),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn constants_are_typed_by_their_value() {
+        register();
+
+        let i8 = Type::int(8, true);
+        let konst = arith::constant(TypedInt(-1, i8));
+        assert_eq!(konst.get_result().ty(), Some(i8));
+        assert_eq!(arith::constant::value(&konst), Some(TypedInt(-1, i8)));
+
+        let ir = lorax::parse(".bb0:\n    %0: i32 := arith.constant {value = 1 : i8}\n").unwrap();
+        let errors = verifier().verify(&ir).unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [Diagnostic {
+                kind: DiagnosticKind::TypeMismatch { name: "result", .. },
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn run_pipeline_by_name() {
+        register();
+
+        let mut ir = lorax::parse(
+            "\
+            .bb0:\n\
+            func.func {sym_name = \"main\"} {\n\
+            .bb1:\n\
+            %0: i32 := arith.constant {value = 2 : i32}\n\
+            %1: i32 := arith.negate %0\n\
+            %2: i32 := arith.negate %1\n\
+            func.ret %2\n\
+            }",
+        )
+        .unwrap();
+
+        let pipeline = PassManager::parse("func.func(canonicalize)", &passes())
+            .unwrap()
+            .verify_each(verifier());
+        pipeline.run(&mut ir).unwrap();
+
+        assert_eq!(
+            ir.to_string(),
+            ".bb0:\n    func.func {sym_name = \"main\"} {\n    .bb1:\n        \
+             %0: i32 := arith.constant {value = 2 : i32}\n        \
+             %1: i32 := arith.negate %0\n        func.ret %0\n    }\n"
+        );
+    }
+}
@@ -1 +1,320 @@
+// Emit AT&T assembly from lowered x86 IR
 
+use std::collections::HashMap;
+use std::fmt::{self, Display, Write};
+
+use lorax::{Block, Operation, Value, link::LinkedList};
+
+use super::ops::{call, imm};
+
+const SLOT_SIZE: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum EmitError {
+    /// An operation that has no x86 equivalent was left in the IR.
+    IllegalOp(&'static str),
+    /// An x86 operation with the wrong operands or attributes.
+    MalformedOp(&'static str),
+    /// Writing out the assembly failed.
+    Fmt(fmt::Error),
+}
+
+impl Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmitError::IllegalOp(name) => write!(f, "'{name}' is not a legal x86 operation"),
+            EmitError::MalformedOp(name) => write!(f, "malformed '{name}' operation"),
+            EmitError::Fmt(e) => write!(f, "couldn't write the assembly: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for EmitError {}
+
+impl From<fmt::Error> for EmitError {
+    fn from(error: fmt::Error) -> Self {
+        EmitError::Fmt(error)
+    }
+}
+
+type EmitResult<T> = Result<T, EmitError>;
+
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Imm(u32),
+    Reg(&'static str),
+    Stack(usize),
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Imm(value) => write!(f, "${value}"),
+            Location::Reg(name) => write!(f, "%{name}"),
+            Location::Stack(offset) => write!(f, "-{offset}(%rbp)"),
+        }
+    }
+}
+
+/// Where each value of a function lives. Values defined by register or
+/// immediate ops map directly onto them, everything else gets a stack slot.
+struct Frame {
+    locations: HashMap<Value, Location>,
+    size: usize,
+}
+
+impl Frame {
+    fn new(body: &Block) -> EmitResult<Self> {
+        let mut frame = Frame {
+            locations: HashMap::new(),
+            size: 0,
+        };
+
+        for op in body.iter() {
+            let &[result] = op.results.as_slice() else {
+                continue;
+            };
+
+            let loc = match op.name {
+                "x86.imm" => match imm::value(op) {
+                    Some(value) => Location::Imm(value),
+                    None => return Err(EmitError::MalformedOp(op.name)),
+                },
+                "x86.ax" => Location::Reg("eax"),
+                "x86.r10" => Location::Reg("r10d"),
+                _ => continue,
+            };
+
+            frame.locations.insert(result, loc);
+        }
+
+        Ok(frame)
+    }
+
+    fn get(&mut self, val: Value) -> Location {
+        *self.locations.entry(val).or_insert_with(|| {
+            self.size += SLOT_SIZE;
+            Location::Stack(self.size)
+        })
+    }
+
+    /// Stack space to reserve, keeping %rsp 16-byte aligned
+    fn stack_size(&self) -> usize {
+        self.size.next_multiple_of(16)
+    }
+}
+
+fn emit_op(out: &mut String, frame: &mut Frame, op: &Operation) -> EmitResult<()> {
+    match (op.name, op.operands.as_slice()) {
+        // these only name a location, which the frame already knows about
+        ("x86.imm" | "x86.ax" | "x86.r10", &[]) => (),
+
+        ("x86.mov", &[src, dst]) => {
+            let (src, dst) = (frame.get(src), frame.get(dst));
+
+            if let (Location::Stack(_), Location::Stack(_)) | (_, Location::Imm(_)) = (src, dst) {
+                return Err(EmitError::MalformedOp(op.name));
+            }
+
+            writeln!(out, "    movl    {src}, {dst}")?;
+        }
+        ("x86.neg" | "x86.not", &[val]) => {
+            // the operand is overwritten, so it can't be an immediate
+            let val = frame.get(val);
+            if let Location::Imm(_) = val {
+                return Err(EmitError::MalformedOp(op.name));
+            }
+
+            let ins = if op.name == "x86.neg" { "negl" } else { "notl" };
+            writeln!(out, "    {ins}    {val}")?;
+        }
+        ("x86.call", &[]) => match call::callee(op) {
+            Some(callee) => writeln!(out, "    call    {}", callee.0)?,
+            None => return Err(EmitError::MalformedOp(op.name)),
+        },
+        ("x86.ret", &[]) => {
+            writeln!(out, "    movq    %rbp, %rsp")?;
+            writeln!(out, "    popq    %rbp")?;
+            writeln!(out, "    ret")?;
+        }
+
+        (name, _) if name.starts_with("x86.") => return Err(EmitError::MalformedOp(name)),
+        (name, _) => return Err(EmitError::IllegalOp(name)),
+    }
+
+    Ok(())
+}
+
+fn emit_func(out: &mut String, name: &str, body: &Block) -> EmitResult<()> {
+    let mut frame = Frame::new(body)?;
+    let mut ins = String::new();
+
+    for op in body.iter() {
+        emit_op(&mut ins, &mut frame, op)?;
+    }
+
+    writeln!(out, "    .globl {name}")?;
+    writeln!(out, "{name}:")?;
+    writeln!(out, "    pushq   %rbp")?;
+    writeln!(out, "    movq    %rsp, %rbp")?;
+
+    if frame.stack_size() > 0 {
+        writeln!(out, "    subq    ${}, %rsp", frame.stack_size())?;
+    }
+
+    write!(out, "{ins}")?;
+
+    Ok(())
+}
+
+/// Emit a module of lowered functions as GNU assembler source.
+///
+/// Fails if anything other than x86 operations is left inside the functions.
+pub fn emit(module: &Block) -> EmitResult<String> {
+    let mut out = String::new();
+
+    for op in module.iter() {
+        match op.name {
+            "func.func" => {
+                let Some(name) = op.sym_name() else {
+                    return Err(EmitError::MalformedOp(op.name));
+                };
+
+                for body in op.walk_blocks() {
+                    emit_func(&mut out, name, body)?;
+                }
+            }
+            name => return Err(EmitError::IllegalOp(name)),
+        }
+    }
+
+    writeln!(out, "\n    .section .note.GNU-stack,\"\",@progbits")?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{arith, func::func, x86::ops::*, x86::state::*};
+    use lorax::attr::SymbolRef;
+
+    fn module(body: Block) -> Block {
+        let mut module = Block::new();
+        module.push(func(body, "main".to_owned()));
+        module
+    }
+
+    fn result(block: &mut Block, op: Operation) -> Value {
+        let ptr = block.push(op);
+        block.get(ptr).get_result()
+    }
+
+    #[test]
+    fn emit_return_constant() {
+        let mut body = Block::new();
+        let val = result(&mut body, imm(2));
+        let eax = result(&mut body, ax());
+        body.push(mov(val, eax));
+        body.push(ret());
+
+        let asm = emit(&module(body)).unwrap();
+
+        assert!(asm.contains("    .globl main\nmain:\n"));
+        assert!(asm.contains("movl    $2, %eax"));
+        assert!(asm.contains("ret"));
+        assert!(asm.contains(".section .note.GNU-stack"));
+        // nothing was spilled, so no stack space is reserved
+        assert!(!asm.contains("subq"));
+    }
+
+    #[test]
+    fn emit_named_functions_and_calls() {
+        let mut two = Block::new();
+        let val = result(&mut two, imm(2));
+        let eax = result(&mut two, ax());
+        two.push(mov(val, eax));
+        two.push(ret());
+
+        let mut main = Block::new();
+        main.push(call(SymbolRef("two".to_owned())));
+        main.push(ret());
+
+        let mut module = Block::new();
+        module.push(func(two, "two".to_owned()));
+        module.push(func(main, "main".to_owned()));
+
+        let asm = emit(&module).unwrap();
+
+        assert!(asm.contains("    .globl two\ntwo:\n"));
+        assert!(asm.contains("    .globl main\nmain:\n"));
+        assert!(asm.contains("call    two"));
+    }
+
+    #[test]
+    fn reject_unnamed_function() {
+        let mut body = Block::new();
+        body.push(ret());
+
+        let mut module = Block::new();
+        let mut unnamed = func(body, String::new());
+        unnamed.attributes.clear();
+        module.push(unnamed);
+
+        assert_eq!(emit(&module), Err(EmitError::MalformedOp("func.func")));
+    }
+
+    #[test]
+    fn emit_stack_slots() {
+        let mut body = Block::new();
+        let val = result(&mut body, imm(2));
+        let tmp = result(&mut body, r10());
+        body.push(mov(val, tmp));
+        body.push(neg(tmp));
+        let spilled = result(&mut body, mov(tmp, Value::new(None)));
+        let eax = result(&mut body, ax());
+        body.push(mov(spilled, eax));
+        body.push(ret());
+
+        let asm = emit(&module(body)).unwrap();
+
+        assert!(asm.contains("subq    $16, %rsp"));
+        assert!(asm.contains("negl    %r10d"));
+        assert!(asm.contains("movl    %r10d, -4(%rbp)"));
+        assert!(asm.contains("movl    -4(%rbp), %eax"));
+    }
+
+    #[test]
+    fn reject_unlowered_ops() {
+        let mut body = Block::new();
+        let val = result(&mut body, imm(2));
+        body.push(arith::negate(val));
+
+        assert_eq!(
+            emit(&module(body)),
+            Err(EmitError::IllegalOp("arith.negate"))
+        );
+    }
+
+    #[test]
+    fn reject_memory_to_memory_mov() {
+        let mut body = Block::new();
+        let (src, dst) = (Value::new(None), Value::new(None));
+        body.push(mov(src, dst));
+
+        assert_eq!(emit(&module(body)), Err(EmitError::MalformedOp("x86.mov")));
+    }
+
+    #[test]
+    fn reject_unary_ops_on_immediates() {
+        for unary in [neg, not] {
+            let mut body = Block::new();
+            let val = result(&mut body, imm(3));
+            let op = unary(val);
+            let name = op.name;
+            body.push(op);
+
+            assert_eq!(emit(&module(body)), Err(EmitError::MalformedOp(name)));
+        }
+    }
+}
@@ -1,22 +1,25 @@
-use lorax::{RewriteRule, RewritingCtx};
+use lorax::rewrite_pattern;
 
-use super::ops::*;
+use super::{ops::*, state::r10};
 
-pub struct LowerBinop;
-impl<'block> RewriteRule<RewritingCtx<'block>> for LowerBinop {
-    fn apply(&self, ctx: &mut RewritingCtx<'block>) {
-        match (ctx.name(), ctx.operands(), ctx.result()) {
-            (name, &[src], Some(dst)) => {
-                let ptr = ctx.insert_behind(mov(src, dst));
-                let ptr = ctx.deref(ptr).get_result();
+rewrite_pattern! {
+    pub struct LowerConst: arith.constant() { value } => {
+        imm(value)
+    }
+
+    // x86 unary ops modify their operand in place, so compute the result
+    // in a scratch register and copy it out
+    pub struct LowerNegate: arith.negate(src) -> dst => {
+        let tmp = r10();
+        mov(src, tmp);
+        neg(tmp);
+        mov(tmp, dst)
+    }
 
-                ctx.replace(match name {
-                    "arith.negate" => neg(ptr),
-                    "arith.complement" => not(ptr),
-                    _ => return (),
-                });
-            }
-            _ => (),
-        }
+    pub struct LowerComplement: arith.complement(src) -> dst => {
+        let tmp = r10();
+        mov(src, tmp);
+        not(tmp);
+        mov(tmp, dst)
     }
 }
@@ -1,19 +1,18 @@
-use lorax::{RewriteRule, RewritingCtx};
+use lorax::rewrite_pattern;
 
 use super::{ops::*, state::ax};
 
-pub struct LowerFunc;
-impl<'block> RewriteRule<RewritingCtx<'block>> for LowerFunc {
-    fn apply(&self, ctx: &mut RewritingCtx<'block>) {
-        match (ctx.name(), ctx.operands()) {
-            ("func.ret", &[val]) => {
-                let v0 = ctx.insert_behind(ax());
-                let v0 = ctx.deref(v0).get_result();
-                let _ = ctx.insert_behind(mov(val, v0));
+rewrite_pattern! {
+    pub struct LowerFunc: func.ret(val) => {
+        let v0 = ax();
+        mov(val, v0);
+        ret()
+    }
 
-                ctx.replace(ret());
-            }
-            _ => (),
-        }
+    // the callee leaves its result in eax
+    pub struct LowerCall: func.call() { callee } -> dst => {
+        call(callee);
+        let v0 = ax();
+        mov(v0, dst)
     }
 }
@@ -1,4 +1,5 @@
-use lorax::{RewriteRuleSet, RewritingCtx};
+use lorax::dialect::Dialect;
+use lorax::{Block, GreedyConfig, Pass, RewriteRuleSet, RewritingCtx, rewrite_greedily};
 
 mod emit;
 mod from_arith;
@@ -6,8 +7,39 @@ mod from_func;
 mod ops;
 mod state;
 
+pub use emit::{EmitError, emit};
+
 pub fn rules<'ctx>() -> RewriteRuleSet<RewritingCtx<'ctx>> {
     RewriteRuleSet::new()
-        .add_rule(from_arith::LowerBinop)
+        .add_rule(from_arith::LowerConst)
+        .add_rule(from_arith::LowerNegate)
+        .add_rule(from_arith::LowerComplement)
         .add_rule(from_func::LowerFunc)
+        .add_rule(from_func::LowerCall)
+}
+
+/// Lower arith and func ops to x86 ones, ready to be emitted
+pub struct Lower;
+
+impl Pass for Lower {
+    fn name(&self) -> &'static str {
+        "x86-lower"
+    }
+
+    fn run(&self, block: &mut Block) -> Result<(), String> {
+        rewrite_greedily(block, rules(), GreedyConfig::default());
+        Ok(())
+    }
+}
+
+pub fn dialect() -> Dialect {
+    Dialect::new("x86")
+        .op(&ops::imm::DEFINITION)
+        .op(&ops::mov::DEFINITION)
+        .op(&ops::neg::DEFINITION)
+        .op(&ops::not::DEFINITION)
+        .op(&ops::call::DEFINITION)
+        .op(&ops::ret::DEFINITION)
+        .op(&state::ax::DEFINITION)
+        .op(&state::r10::DEFINITION)
 }
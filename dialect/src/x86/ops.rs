@@ -1,17 +1,28 @@
+use lorax::attr::SymbolRef;
 use lorax::{Operation, Value, def_op};
 
 def_op! {
-    x86.mov(src: Value, dst: Value) -> dst
+    x86.imm() {
+        value: u32
+    } where result: Int [Pure]
 }
 
 def_op! {
-    x86.neg(src: Value) -> src
+    x86.mov(src: Value, dst: Value) -> dst [HasSideEffects]
 }
 
 def_op! {
-    x86.not(src: Value) -> src
+    x86.neg(src: Value) -> src [HasSideEffects]
 }
 
 def_op! {
-    x86.ret() -> None
+    x86.not(src: Value) -> src [HasSideEffects]
+}
+
+def_op! {
+    x86.call() { callee: SymbolRef } -> None [HasSideEffects]
+}
+
+def_op! {
+    x86.ret() -> None [Terminator, HasSideEffects]
 }
@@ -1,9 +1,9 @@
 use lorax::{Operation, Value, def_op};
 
 def_op! {
-    x86.ax()
+    x86.ax() where result: Exact("i32")
 }
 
 def_op! {
-    x86.r10()
+    x86.r10() where result: Exact("i32")
 }
@@ -0,0 +1,25 @@
+use test_each_file::test_each_file;
+
+use dialect::x86;
+use lorax::rewrite_ops;
+
+test_each_file! { in "dialect/tests/x86/" => test_lower_and_emit }
+fn test_lower_and_emit(src: &str) {
+    let mut ir = lorax::parse(src).expect("fixtures should be valid IR");
+    dialect::verifier().verify(&ir).unwrap();
+
+    rewrite_ops(&mut ir, x86::rules());
+    dialect::verifier().verify(&ir).unwrap();
+
+    let lowered = ir.to_string();
+    assert!(
+        !lowered.contains("arith."),
+        "unlowered ops left in\n{lowered}"
+    );
+    assert!(
+        !lowered.contains("func.ret"),
+        "unlowered ops left in\n{lowered}"
+    );
+
+    x86::emit(&ir).expect("lowered IR should be legal x86");
+}
@@ -0,0 +1,414 @@
+// Which blocks of a region every path to (or from) another goes through
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use super::{Analysis, successors};
+use crate::Block;
+
+/// The dominator tree of a region: block `a` dominates `b` when every path
+/// from the entry to `b` goes through `a`.
+///
+/// Blocks are named by their ids. Blocks no path from the entry reaches are
+/// in no tree, and dominate and are dominated by nothing but themselves.
+pub struct DominatorTree {
+    ids: Vec<usize>,
+    index: HashMap<usize, usize>,
+
+    idom: Vec<Option<usize>>,
+    roots: Vec<usize>,
+    children: Vec<Vec<usize>>,
+
+    // numbered by a walk of the tree, a block dominates the ones numbered
+    // within its own span
+    pre: Vec<usize>,
+    post: Vec<usize>,
+    in_tree: Vec<bool>,
+}
+
+impl DominatorTree {
+    pub fn new(region: &[Block]) -> Self {
+        let succs = successors(region);
+
+        let idom = if region.is_empty() {
+            Vec::new()
+        } else {
+            immediate_dominators(&succs, 0)
+        };
+
+        Self::from_idoms(region, idom, &[0])
+    }
+
+    /// The tree where blocks hang off their immediate dominators in `idom`,
+    /// rooted in the blocks of `roots` that are in it
+    fn from_idoms(region: &[Block], idom: Vec<Option<usize>>, roots: &[usize]) -> Self {
+        let len = region.len();
+        let mut children = vec![Vec::new(); len];
+
+        for (block, idom) in idom.iter().enumerate() {
+            if let Some(idom) = *idom {
+                children[idom].push(block);
+            }
+        }
+
+        let mut tree = Self {
+            ids: region.iter().map(Block::id).collect(),
+            index: region
+                .iter()
+                .enumerate()
+                .map(|(idx, block)| (block.id(), idx))
+                .collect(),
+
+            idom,
+            roots: roots.iter().copied().filter(|&root| root < len).collect(),
+            children,
+
+            pre: vec![0; len],
+            post: vec![0; len],
+            in_tree: vec![false; len],
+        };
+
+        tree.number();
+        tree
+    }
+
+    fn number(&mut self) {
+        let mut counter = 0;
+        let mut stack: Vec<(usize, usize)> = self.roots.iter().rev().map(|&r| (r, 0)).collect();
+
+        for &root in &self.roots {
+            self.in_tree[root] = true;
+        }
+
+        while let Some((block, child)) = stack.pop() {
+            if child == 0 {
+                self.pre[block] = counter;
+                counter += 1;
+            }
+
+            match self.children[block].get(child) {
+                Some(&next) => {
+                    stack.push((block, child + 1));
+                    stack.push((next, 0));
+                    self.in_tree[next] = true;
+                }
+                None => {
+                    self.post[block] = counter;
+                    counter += 1;
+                }
+            }
+        }
+    }
+
+    fn idx(&self, block: usize) -> Option<usize> {
+        self.index.get(&block).copied()
+    }
+
+    /// The closest block dominating `block` other than itself
+    pub fn idom(&self, block: usize) -> Option<usize> {
+        let idom = self.idom[self.idx(block)?]?;
+        Some(self.ids[idom])
+    }
+
+    /// Whether `a` dominates `b`, which every block does to itself
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        let (Some(a), Some(b)) = (self.idx(a), self.idx(b)) else {
+            return false;
+        };
+
+        a == b
+            || (self.in_tree[a]
+                && self.in_tree[b]
+                && self.pre[a] <= self.pre[b]
+                && self.post[b] <= self.post[a])
+    }
+
+    /// Whether `a` dominates `b` and isn't `b`
+    pub fn properly_dominates(&self, a: usize, b: usize) -> bool {
+        a != b && self.dominates(a, b)
+    }
+
+    /// Whether the block is in the tree, e.g. is reachable from the entry
+    pub fn contains(&self, block: usize) -> bool {
+        self.idx(block).is_some_and(|idx| self.in_tree[idx])
+    }
+
+    /// The blocks `block` is the immediate dominator of
+    pub fn children(&self, block: usize) -> impl Iterator<Item = usize> + '_ {
+        self.idx(block)
+            .map_or(&[][..], |idx| self.children[idx].as_slice())
+            .iter()
+            .map(|&child| self.ids[child])
+    }
+
+    /// The blocks of the tree, each before the blocks it dominates
+    pub fn preorder(&self) -> Vec<usize> {
+        let mut order: Vec<_> = (0..self.ids.len())
+            .filter(|&idx| self.in_tree[idx])
+            .collect();
+        order.sort_by_key(|&idx| self.pre[idx]);
+        order.into_iter().map(|idx| self.ids[idx]).collect()
+    }
+}
+
+impl Analysis for DominatorTree {
+    fn compute(region: &[Block]) -> Self {
+        Self::new(region)
+    }
+}
+
+/// The post-dominator tree of a region: block `a` post-dominates `b` when
+/// every path from `b` to a block without successors goes through `a`.
+///
+/// Each block without successors roots a tree of its own, and blocks that
+/// reach none of them are in no tree.
+pub struct PostDominatorTree(DominatorTree);
+
+impl PostDominatorTree {
+    pub fn new(region: &[Block]) -> Self {
+        let succs = successors(region);
+        let len = region.len();
+
+        // the reversed graph, with a virtual node at `len` leading to every exit
+        let mut preds = vec![Vec::new(); len + 1];
+        for (block, succs) in succs.iter().enumerate() {
+            for &succ in succs {
+                preds[succ].push(block);
+            }
+        }
+
+        let exits: Vec<_> = (0..len).filter(|&block| succs[block].is_empty()).collect();
+        preds[len] = exits.clone();
+
+        let mut idom = immediate_dominators(&preds, len);
+        idom.pop();
+        for idom in &mut idom {
+            if *idom == Some(len) {
+                *idom = None;
+            }
+        }
+
+        Self(DominatorTree::from_idoms(region, idom, &exits))
+    }
+}
+
+/// Asking whether `a` dominates `b` asks whether `a` post-dominates `b`
+impl Deref for PostDominatorTree {
+    type Target = DominatorTree;
+
+    fn deref(&self) -> &DominatorTree {
+        &self.0
+    }
+}
+
+impl Analysis for PostDominatorTree {
+    fn compute(region: &[Block]) -> Self {
+        Self::new(region)
+    }
+}
+
+/// The immediate dominator of each node of a graph, by the iterative algorithm
+/// of Cooper, Harvey and Kennedy. The root and nodes it doesn't reach have none.
+fn immediate_dominators(succs: &[Vec<usize>], root: usize) -> Vec<Option<usize>> {
+    let len = succs.len();
+
+    // postorder from the root
+    let mut post = vec![None; len];
+    let mut order = Vec::new();
+    let mut stack = vec![(root, 0)];
+    let mut seen = vec![false; len];
+    seen[root] = true;
+
+    while let Some((node, child)) = stack.pop() {
+        match succs[node].get(child) {
+            Some(&next) => {
+                stack.push((node, child + 1));
+
+                if !seen[next] {
+                    seen[next] = true;
+                    stack.push((next, 0));
+                }
+            }
+            None => {
+                post[node] = Some(order.len());
+                order.push(node);
+            }
+        }
+    }
+
+    let mut preds = vec![Vec::new(); len];
+    for (node, succs) in succs.iter().enumerate() {
+        for &succ in succs {
+            preds[succ].push(node);
+        }
+    }
+
+    let mut idom = vec![None; len];
+    idom[root] = Some(root);
+
+    let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+        while a != b {
+            while post[a] < post[b] {
+                a = idom[a].expect("nodes being intersected have dominators");
+            }
+            while post[b] < post[a] {
+                b = idom[b].expect("nodes being intersected have dominators");
+            }
+        }
+        a
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for &node in order.iter().rev().filter(|&&node| node != root) {
+            let mut new = None;
+
+            for &pred in &preds[node] {
+                if idom[pred].is_some() {
+                    new = Some(match new {
+                        None => pred,
+                        Some(new) => intersect(&idom, pred, new),
+                    });
+                }
+            }
+
+            if idom[node] != new {
+                idom[node] = new;
+                changed = true;
+            }
+        }
+    }
+
+    idom[root] = None;
+    idom
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::link::LinkedList;
+    use crate::parse;
+
+    /// The region of the first op, and the ids of its blocks
+    fn parse_region(src: &str) -> (Vec<Block>, Vec<usize>) {
+        let mut block = parse(src).unwrap();
+        let op = block.head().unwrap();
+        let region = std::mem::take(&mut block.get_mut(op).blocks);
+        let ids = region.iter().map(Block::id).collect();
+        (region, ids)
+    }
+
+    // .bb1 branches to .bb2 and .bb3, which both go on to .bb4, and .bb5 is unreachable
+    const DIAMOND: &str = "\
+.bb0:
+    test.region {
+    .bb1:
+        test.br .bb2, .bb3
+    .bb2:
+        test.br .bb4
+    .bb3:
+        test.br .bb4
+    .bb4:
+        test.ret
+    .bb5:
+        test.br .bb4
+    }
+";
+
+    #[test]
+    fn dominate_a_diamond() {
+        let (region, b) = parse_region(DIAMOND);
+        let dom = DominatorTree::new(&region);
+
+        assert_eq!(dom.idom(b[0]), None);
+        assert_eq!(dom.idom(b[1]), Some(b[0]));
+        assert_eq!(dom.idom(b[2]), Some(b[0]));
+        assert_eq!(dom.idom(b[3]), Some(b[0]));
+
+        assert!(dom.dominates(b[0], b[3]));
+        assert!(dom.dominates(b[3], b[3]));
+        assert!(!dom.properly_dominates(b[3], b[3]));
+        assert!(!dom.dominates(b[1], b[3]));
+        assert!(!dom.dominates(b[3], b[1]));
+
+        assert!(!dom.contains(b[4]));
+        assert!(!dom.dominates(b[0], b[4]));
+        assert_eq!(dom.children(b[0]).collect::<Vec<_>>(), [b[1], b[2], b[3]]);
+        assert_eq!(dom.preorder(), [b[0], b[1], b[2], b[3]]);
+    }
+
+    #[test]
+    fn dominate_loops() {
+        let (region, b) = parse_region(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        test.br .bb2
+    .bb2:
+        test.br .bb3, .bb4
+    .bb3:
+        test.br .bb2
+    .bb4:
+        test.ret
+    }
+",
+        );
+        let dom = DominatorTree::new(&region);
+
+        assert_eq!(dom.idom(b[1]), Some(b[0]));
+        assert_eq!(dom.idom(b[2]), Some(b[1]));
+        assert_eq!(dom.idom(b[3]), Some(b[1]));
+        assert!(dom.dominates(b[1], b[2]));
+        assert!(!dom.dominates(b[2], b[1]));
+
+        let post = PostDominatorTree::new(&region);
+        assert_eq!(post.idom(b[0]), Some(b[1]));
+        assert_eq!(post.idom(b[2]), Some(b[1]));
+        assert_eq!(post.idom(b[1]), Some(b[3]));
+        assert!(post.dominates(b[3], b[0]));
+    }
+
+    #[test]
+    fn post_dominate_a_diamond() {
+        let (region, b) = parse_region(DIAMOND);
+        let post = PostDominatorTree::new(&region);
+
+        assert_eq!(post.idom(b[3]), None);
+        assert_eq!(post.idom(b[0]), Some(b[3]));
+        assert_eq!(post.idom(b[1]), Some(b[3]));
+        assert_eq!(post.idom(b[4]), Some(b[3]));
+        assert!(post.dominates(b[3], b[0]));
+        assert!(!post.dominates(b[1], b[0]));
+    }
+
+    #[test]
+    fn post_dominate_several_exits() {
+        let (region, b) = parse_region(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        test.br .bb2, .bb3
+    .bb2:
+        test.ret
+    .bb3:
+        test.br .bb3
+    .bb4:
+        test.ret
+    }
+",
+        );
+        let post = PostDominatorTree::new(&region);
+
+        // the paths from .bb1 that end at all go through .bb2, and .bb3 never ends
+        assert_eq!(post.idom(b[0]), Some(b[1]));
+        assert!(post.dominates(b[1], b[0]));
+        assert!(!post.contains(b[2]));
+        assert!(post.contains(b[3]));
+        assert!(!post.dominates(b[3], b[0]));
+        assert_eq!(post.preorder(), [b[1], b[0], b[3]]);
+    }
+}
@@ -0,0 +1,330 @@
+// Where in a region each of its values is still going to be used
+
+use std::collections::{HashMap, HashSet};
+
+use super::{Analysis, successors};
+use crate::{Block, Operation, Value};
+
+/// Where a value is live in one block, as positions in it: 0 is the start of
+/// the block, where its arguments are defined, and the op at index `i` in the
+/// block is at `i + 1`.
+///
+/// The value is live from `start`, where it's defined or the block starts,
+/// up to `end`, its last use or one past the last op if it's live out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LiveSegment {
+    pub block: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LiveSegment {
+    fn overlaps(&self, other: &LiveSegment) -> bool {
+        self.block == other.block && self.start < other.end && other.start < self.end
+    }
+}
+
+/// Which values of a region are live going into and out of each of its
+/// blocks, and where each one is live.
+///
+/// Only the values defined in the region count, by its ops or as the
+/// arguments of its blocks. A use in a block nested in an op counts as a use
+/// by the op.
+pub struct Liveness {
+    live_in: HashMap<usize, HashSet<Value>>,
+    live_out: HashMap<usize, HashSet<Value>>,
+    ranges: HashMap<Value, Vec<LiveSegment>>,
+}
+
+/// The values an op uses, including in the blocks nested in it
+fn uses(op: &Operation, uses: &mut Vec<Value>) {
+    uses.extend(op.all_operands().copied());
+
+    for block in op.walk_blocks() {
+        for op in block.walk_ops() {
+            self::uses(op, uses);
+        }
+    }
+}
+
+impl Liveness {
+    pub fn new(region: &[Block]) -> Self {
+        let succs = successors(region);
+
+        // every value defined in the region, by the block it's defined in
+        let mut defs = vec![HashSet::new(); region.len()];
+        for (block, defs) in region.iter().zip(&mut defs) {
+            defs.extend(block.args().iter().copied());
+            for op in block.walk_ops() {
+                defs.extend(op.results.iter().copied());
+            }
+        }
+        let defined: HashSet<Value> = defs.iter().flatten().copied().collect();
+
+        // the values used in each block before being defined there
+        let upward_exposed: Vec<HashSet<Value>> = region
+            .iter()
+            .map(|block| {
+                let mut exposed = HashSet::new();
+                let mut seen: HashSet<Value> = block.args().iter().copied().collect();
+
+                for op in block.walk_ops() {
+                    let mut used = Vec::new();
+                    uses(op, &mut used);
+
+                    for val in used {
+                        if defined.contains(&val) && !seen.contains(&val) {
+                            exposed.insert(val);
+                        }
+                    }
+
+                    seen.extend(op.results.iter().copied());
+                }
+
+                exposed
+            })
+            .collect();
+
+        let mut live_in = upward_exposed.clone();
+        let mut live_out = vec![HashSet::new(); region.len()];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for block in (0..region.len()).rev() {
+                let out: HashSet<Value> = succs[block]
+                    .iter()
+                    .flat_map(|&succ| live_in[succ].iter().copied())
+                    .collect();
+
+                let mut into = upward_exposed[block].clone();
+                into.extend(out.difference(&defs[block]).copied());
+
+                if out != live_out[block] || into != live_in[block] {
+                    live_out[block] = out;
+                    live_in[block] = into;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut ranges: HashMap<Value, Vec<LiveSegment>> = HashMap::new();
+
+        for (idx, block) in region.iter().enumerate() {
+            let end = block.walk_ops().count() + 1;
+            let mut segments: HashMap<Value, LiveSegment> = HashMap::new();
+
+            for &val in live_in[idx].iter().chain(block.args()) {
+                segments.insert(
+                    val,
+                    LiveSegment {
+                        block: block.id(),
+                        start: 0,
+                        end: 0,
+                    },
+                );
+            }
+
+            for (position, op) in (1..).zip(block.walk_ops()) {
+                let mut used = Vec::new();
+                uses(op, &mut used);
+
+                for val in used {
+                    if let Some(segment) = segments.get_mut(&val) {
+                        segment.end = position;
+                    }
+                }
+
+                for &result in &op.results {
+                    segments.entry(result).or_insert(LiveSegment {
+                        block: block.id(),
+                        start: position,
+                        end: position,
+                    });
+                }
+            }
+
+            for val in &live_out[idx] {
+                if let Some(segment) = segments.get_mut(val) {
+                    segment.end = end;
+                }
+            }
+
+            for (val, segment) in segments {
+                ranges.entry(val).or_default().push(segment);
+            }
+        }
+
+        let ids = region.iter().map(Block::id);
+
+        Self {
+            live_in: ids.clone().zip(live_in).collect(),
+            live_out: ids.zip(live_out).collect(),
+            ranges,
+        }
+    }
+
+    /// The values live when the block starts, other than its arguments
+    pub fn live_in(&self, block: usize) -> &HashSet<Value> {
+        &self.live_in[&block]
+    }
+
+    /// The values live when the block ends, still to be used by a block after it
+    pub fn live_out(&self, block: usize) -> &HashSet<Value> {
+        &self.live_out[&block]
+    }
+
+    /// Where the value is live, a segment per block in region order
+    pub fn range(&self, val: Value) -> &[LiveSegment] {
+        let mut range = self.ranges.get(&val).map_or(&[][..], Vec::as_slice);
+
+        // a value defined and never used is live nowhere
+        if range.iter().all(|segment| segment.start == segment.end) {
+            range = &[];
+        }
+
+        range
+    }
+
+    /// Whether both values are live somewhere at once, so they can't share a register
+    pub fn interfere(&self, a: Value, b: Value) -> bool {
+        self.range(a)
+            .iter()
+            .any(|a| self.range(b).iter().any(|b| a.overlaps(b)))
+    }
+}
+
+impl Analysis for Liveness {
+    fn compute(region: &[Block]) -> Self {
+        Self::new(region)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::link::LinkedList;
+    use crate::parse;
+
+    fn parse_region(src: &str) -> Vec<Block> {
+        let mut block = parse(src).unwrap();
+        let op = block.head().unwrap();
+        std::mem::take(&mut block.get_mut(op).blocks)
+    }
+
+    fn result(block: &Block, idx: usize) -> Value {
+        block.walk_ops().nth(idx).unwrap().get_result()
+    }
+
+    #[test]
+    fn live_across_blocks() {
+        let region = parse_region(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const
+        %1 := test.const
+        test.br .bb2, .bb3
+    .bb2:
+        %2 := test.neg %0
+        test.br .bb4(%2)
+    .bb3:
+        test.br .bb4(%1)
+    .bb4(%3):
+        test.ret %3, %0
+    }
+",
+        );
+        let live = Liveness::new(&region);
+        let ids: Vec<_> = region.iter().map(Block::id).collect();
+        let (v0, v1) = (result(&region[0], 0), result(&region[0], 1));
+        let v2 = result(&region[1], 0);
+        let v3 = region[3].args()[0];
+
+        assert!(live.live_in(ids[0]).is_empty());
+        assert_eq!(*live.live_out(ids[0]), HashSet::from([v0, v1]));
+        assert_eq!(*live.live_in(ids[1]), HashSet::from([v0]));
+        assert_eq!(*live.live_out(ids[1]), HashSet::from([v0]));
+        assert_eq!(*live.live_in(ids[2]), HashSet::from([v0, v1]));
+        assert_eq!(*live.live_in(ids[3]), HashSet::from([v0]));
+        assert!(live.live_out(ids[3]).is_empty());
+
+        // %2 is defined by the first op of .bb2 and used by the second
+        assert_eq!(
+            live.range(v2),
+            [LiveSegment {
+                block: ids[1],
+                start: 1,
+                end: 2
+            }]
+        );
+        assert_eq!(live.range(v3).len(), 1);
+        assert_eq!(live.range(v0).len(), 4);
+
+        assert!(live.interfere(v0, v2));
+        assert!(!live.interfere(v1, v2));
+        assert!(!live.interfere(v2, v3));
+    }
+
+    #[test]
+    fn live_around_loops() {
+        let region = parse_region(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const
+        test.br .bb2
+    .bb2:
+        %1 := test.neg %0
+        test.br .bb2, .bb3
+    .bb3:
+        test.ret %1
+    }
+",
+        );
+        let live = Liveness::new(&region);
+        let ids: Vec<_> = region.iter().map(Block::id).collect();
+        let v0 = result(&region[0], 0);
+        let v1 = result(&region[1], 0);
+
+        // the loop uses %0 again on every trip around it
+        assert_eq!(*live.live_in(ids[1]), HashSet::from([v0]));
+        assert_eq!(*live.live_out(ids[1]), HashSet::from([v0, v1]));
+        assert_eq!(
+            live.range(v0)[1],
+            LiveSegment {
+                block: ids[1],
+                start: 0,
+                end: 3
+            }
+        );
+    }
+
+    #[test]
+    fn uses_in_nested_blocks_count() {
+        let region = parse_region(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const
+        %1 := test.const
+        test.loop {
+        .bb2:
+            test.use %0
+        }
+        test.ret
+    }
+",
+        );
+        let live = Liveness::new(&region);
+        let (v0, v1) = (result(&region[0], 0), result(&region[0], 1));
+
+        assert_eq!(live.range(v0)[0].end, 3);
+        assert!(live.range(v1).is_empty());
+        assert!(!live.interfere(v0, v1));
+    }
+}
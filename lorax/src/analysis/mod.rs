@@ -0,0 +1,185 @@
+// Facts computed about the blocks of a region, cached until the region changes
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use crate::Block;
+
+mod dominance;
+mod liveness;
+
+pub use dominance::{DominatorTree, PostDominatorTree};
+pub use liveness::{LiveSegment, Liveness};
+
+/// Something computed from a region, the blocks of an op, that can be cached
+/// by an `AnalysisManager`.
+///
+/// The first block is the region's entry, and the blocks reach each other
+/// through the successors of their ops.
+pub trait Analysis: 'static {
+    fn compute(region: &[Block]) -> Self
+    where
+        Self: Sized;
+}
+
+/// The successors of each block of the region, as indices into it.
+/// Successors naming a block outside the region are left out.
+pub(crate) fn successors(region: &[Block]) -> Vec<Vec<usize>> {
+    let index: HashMap<_, _> = region
+        .iter()
+        .enumerate()
+        .map(|(idx, block)| (block.id(), idx))
+        .collect();
+
+    region
+        .iter()
+        .map(|block| {
+            let mut succs = Vec::new();
+
+            for op in block.walk_ops() {
+                for successor in &op.successors {
+                    if let Some(&idx) = index.get(&successor.block)
+                        && !succs.contains(&idx)
+                    {
+                        succs.push(idx);
+                    }
+                }
+            }
+
+            succs
+        })
+        .collect()
+}
+
+/// Analyses of regions, computed when they're first asked for and kept until
+/// they're invalidated.
+///
+/// Regions are told apart by the id of their entry block, so the manager
+/// can't tell that a region changed: whatever changes it has to invalidate
+/// its analyses.
+#[derive(Default)]
+pub struct AnalysisManager {
+    cache: HashMap<(TypeId, Option<usize>), Box<dyn Any>>,
+}
+
+impl AnalysisManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The analysis of the region, computing it unless it's cached
+    pub fn get<A: Analysis>(&mut self, region: &[Block]) -> &A {
+        self.cache
+            .entry(key::<A>(region))
+            .or_insert_with(|| Box::new(A::compute(region)))
+            .downcast_ref()
+            .expect("analyses are cached by their type")
+    }
+
+    /// The analysis of the region, if it's cached
+    pub fn cached<A: Analysis>(&self, region: &[Block]) -> Option<&A> {
+        self.cache
+            .get(&key::<A>(region))
+            .and_then(|analysis| analysis.downcast_ref())
+    }
+
+    /// Drop every analysis of the region, after it was changed
+    pub fn invalidate(&mut self, region: &[Block]) {
+        let entry = entry(region);
+        self.cache.retain(|&(_, region), _| region != entry);
+    }
+
+    /// Drop the analyses of the region, except for those the change kept valid
+    pub fn invalidate_except(&mut self, region: &[Block], preserved: &[TypeId]) {
+        let entry = entry(region);
+        self.cache
+            .retain(|&(analysis, region), _| region != entry || preserved.contains(&analysis));
+    }
+
+    /// Drop every analysis of every region
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+}
+
+fn entry(region: &[Block]) -> Option<usize> {
+    region.first().map(Block::id)
+}
+
+fn key<A: Analysis>(region: &[Block]) -> (TypeId, Option<usize>) {
+    (TypeId::of::<A>(), entry(region))
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::link::LinkedList;
+    use crate::parse;
+
+    thread_local! {
+        static COMPUTED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// How many ops the region has, counting how often it was computed
+    struct OpCount(usize);
+
+    impl Analysis for OpCount {
+        fn compute(region: &[Block]) -> Self {
+            COMPUTED.set(COMPUTED.get() + 1);
+            OpCount(region.iter().map(|block| block.iter().count()).sum())
+        }
+    }
+
+    fn parse_region(src: &str) -> Vec<Block> {
+        let mut block = parse(src).unwrap();
+        let op = block.head().unwrap();
+        std::mem::take(&mut block.get_mut(op).blocks)
+    }
+
+    #[test]
+    fn cache_until_invalidated() {
+        let region = parse_region(".bb0:\ntest.region {\n.bb1:\ntest.a\n.bb2:\ntest.b\n}");
+        let other = parse_region(".bb0:\ntest.region {\n.bb1:\ntest.a\n}");
+        let mut analyses = AnalysisManager::new();
+
+        assert!(analyses.cached::<OpCount>(&region).is_none());
+        assert_eq!(analyses.get::<OpCount>(&region).0, 2);
+        assert_eq!(analyses.get::<OpCount>(&region).0, 2);
+        assert_eq!(analyses.get::<OpCount>(&other).0, 1);
+        assert_eq!(COMPUTED.get(), 2);
+
+        analyses.invalidate_except(&region, &[TypeId::of::<OpCount>()]);
+        assert!(analyses.cached::<OpCount>(&region).is_some());
+
+        analyses.invalidate(&region);
+        assert!(analyses.cached::<OpCount>(&region).is_none());
+        assert!(analyses.cached::<OpCount>(&other).is_some());
+
+        analyses.get::<OpCount>(&region);
+        assert_eq!(COMPUTED.get(), 3);
+
+        analyses.clear();
+        assert!(analyses.cached::<OpCount>(&other).is_none());
+    }
+
+    #[test]
+    fn find_successors_in_the_region() {
+        let region = parse_region(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        test.br .bb2, .bb3
+    .bb2:
+        test.br .bb3
+    .bb3:
+        test.ret
+    }
+",
+        );
+
+        assert_eq!(successors(&region), [vec![1, 2], vec![2], vec![]]);
+    }
+}
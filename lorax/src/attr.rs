@@ -1,8 +1,299 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt::Display;
 
-#[derive(Debug)]
+use crate::types::{Type, TypeKind};
+
+/// Constant data attached to an op.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Attribute {
-    Int(u32),
+    /// An integer of an integer type, printed like `5 : u8`. `i64` is the
+    /// default, those are printed without their type.
+    Int(i128, Type),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    /// A reference to a symbol by its name, printed like `@main`
+    Symbol(String),
+    Type(Type),
+    Array(Vec<Attribute>),
+    Dict(AttributeMap),
+}
+
+impl Attribute {
+    pub fn kind(&self) -> AttrKind {
+        match self {
+            Attribute::Int(..) => AttrKind::Int,
+            Attribute::Float(_) => AttrKind::Float,
+            Attribute::Str(_) => AttrKind::Str,
+            Attribute::Bool(_) => AttrKind::Bool,
+            Attribute::Symbol(_) => AttrKind::Symbol,
+            Attribute::Type(_) => AttrKind::Type,
+            Attribute::Array(_) => AttrKind::Array,
+            Attribute::Dict(_) => AttrKind::Dict,
+        }
+    }
+
+    /// The type of an integer attribute
+    pub fn int_type(&self) -> Option<Type> {
+        match *self {
+            Attribute::Int(_, ty) => Some(ty),
+            _ => None,
+        }
+    }
+
+    /// Whether `value` fits in an integer attribute of type `ty`
+    pub fn int_fits(value: i128, ty: Type) -> bool {
+        match *ty.kind() {
+            TypeKind::Int { width, signed } => {
+                let bits = if signed { width - 1 } else { width };
+                let max = 1i128 << bits;
+                let min = if signed { -max } else { 0 };
+                (min..max).contains(&value)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The kind of value an attribute holds, without the value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttrKind {
+    Int,
+    Float,
+    Str,
+    Bool,
+    Symbol,
+    Type,
+    Array,
+    Dict,
+}
+
+impl Display for AttrKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AttrKind::Int => "an integer",
+            AttrKind::Float => "a float",
+            AttrKind::Str => "a string",
+            AttrKind::Bool => "a boolean",
+            AttrKind::Symbol => "a symbol",
+            AttrKind::Type => "a type",
+            AttrKind::Array => "an array",
+            AttrKind::Dict => "a dictionary",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+pub(crate) fn fmt_str(s: &str, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "\"")?;
+
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+pub(crate) fn fmt_dict(dict: &AttributeMap, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{{")?;
+
+    for (i, (key, attr)) in dict.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{} = {}", key, attr)?;
+    }
+
+    write!(f, "}}")
+}
+
+impl Display for Attribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Attribute::Int(value, ty) if *ty == Type::int(64, true) => write!(f, "{}", value),
+            Attribute::Int(value, ty) => write!(f, "{} : {}", value, ty),
+            Attribute::Float(value) if value.is_nan() => write!(f, "nan"),
+            Attribute::Float(value) if value.is_infinite() => {
+                write!(f, "{}inf", if *value < 0.0 { "-" } else { "" })
+            }
+            // always with a point or an exponent, so it reads back as a float
+            Attribute::Float(value) => write!(f, "{:?}", value),
+            Attribute::Str(s) => fmt_str(s, f),
+            Attribute::Bool(value) => write!(f, "{}", value),
+            Attribute::Symbol(name) => write!(f, "@{}", name),
+            Attribute::Type(ty) => write!(f, "{}", ty),
+            Attribute::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Attribute::Dict(dict) => fmt_dict(dict, f),
+        }
+    }
+}
+
+// ordered so that printed IR is stable
+pub type AttributeMap = BTreeMap<String, Attribute>;
+
+/// The name of a symbol, as the Rust type of a symbol reference attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolRef(pub String);
+
+/// An integer together with its type, as the Rust type of an integer
+/// attribute whose type matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypedInt(pub i128, pub Type);
+
+/// A Rust type that can be stored in an attribute, and read back from one.
+///
+/// `def_op!` uses this for the attributes it declares, to build them from the
+/// constructor's arguments and to generate accessors.
+pub trait AttrValue: Sized {
+    const KIND: AttrKind;
+
+    fn into_attr(self) -> Attribute;
+
+    fn from_attr(attr: &Attribute) -> Option<Self>;
 }
 
-pub type AttributeMap = HashMap<String, Attribute>;
+macro_rules! int_attr_value {
+    ($($ty:ident: $width:literal, $signed:literal;)*) => {$(
+        impl AttrValue for $ty {
+            const KIND: AttrKind = AttrKind::Int;
+
+            fn into_attr(self) -> Attribute {
+                Attribute::Int(self.into(), Type::int($width, $signed))
+            }
+
+            // integers of any type convert, as long as the value fits
+            fn from_attr(attr: &Attribute) -> Option<Self> {
+                match attr {
+                    Attribute::Int(value, _) => (*value).try_into().ok(),
+                    _ => None,
+                }
+            }
+        }
+    )*};
+}
+
+int_attr_value! {
+    i8: 8, true;
+    i16: 16, true;
+    i32: 32, true;
+    i64: 64, true;
+    u8: 8, false;
+    u16: 16, false;
+    u32: 32, false;
+    u64: 64, false;
+}
+
+macro_rules! attr_value {
+    ($($ty:ty: $variant:ident;)*) => {$(
+        impl AttrValue for $ty {
+            const KIND: AttrKind = AttrKind::$variant;
+
+            fn into_attr(self) -> Attribute {
+                Attribute::$variant(self)
+            }
+
+            fn from_attr(attr: &Attribute) -> Option<Self> {
+                match attr {
+                    Attribute::$variant(value) => Some(value.clone()),
+                    _ => None,
+                }
+            }
+        }
+    )*};
+}
+
+attr_value! {
+    f64: Float;
+    String: Str;
+    bool: Bool;
+    Type: Type;
+    Vec<Attribute>: Array;
+    AttributeMap: Dict;
+}
+
+impl AttrValue for SymbolRef {
+    const KIND: AttrKind = AttrKind::Symbol;
+
+    fn into_attr(self) -> Attribute {
+        Attribute::Symbol(self.0)
+    }
+
+    fn from_attr(attr: &Attribute) -> Option<Self> {
+        match attr {
+            Attribute::Symbol(name) => Some(SymbolRef(name.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl AttrValue for TypedInt {
+    const KIND: AttrKind = AttrKind::Int;
+
+    fn into_attr(self) -> Attribute {
+        Attribute::Int(self.0, self.1)
+    }
+
+    fn from_attr(attr: &Attribute) -> Option<Self> {
+        match *attr {
+            Attribute::Int(value, ty) => Some(TypedInt(value, ty)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert_rust_values() {
+        let attr = 200u8.into_attr();
+        assert_eq!(attr, Attribute::Int(200, Type::int(8, false)));
+        assert_eq!(u32::from_attr(&attr), Some(200));
+        assert_eq!(i8::from_attr(&attr), None);
+        assert_eq!(bool::from_attr(&attr), None);
+
+        let typed = TypedInt(-1, Type::int(16, true));
+        assert_eq!(TypedInt::from_attr(&typed.into_attr()), Some(typed));
+        assert_eq!(
+            TypedInt::from_attr(&attr),
+            Some(TypedInt(200, Type::int(8, false)))
+        );
+
+        let sym = SymbolRef("main".to_owned());
+        assert_eq!(SymbolRef::from_attr(&sym.clone().into_attr()), Some(sym));
+        assert_eq!(<Vec<Attribute>>::KIND, AttrKind::Array);
+    }
+
+    #[test]
+    fn int_range() {
+        let (i8, u8) = (Type::int(8, true), Type::int(8, false));
+
+        assert!(Attribute::int_fits(-128, i8));
+        assert!(!Attribute::int_fits(128, i8));
+        assert!(Attribute::int_fits(255, u8));
+        assert!(!Attribute::int_fits(-1, u8));
+        assert!(Attribute::int_fits(u64::MAX.into(), Type::int(64, false)));
+        assert!(!Attribute::int_fits(
+            i64::MAX as i128 + 1,
+            Type::int(64, true)
+        ));
+        assert!(!Attribute::int_fits(0, Type::ptr(u8)));
+    }
+}
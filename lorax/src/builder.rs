@@ -0,0 +1,214 @@
+// Create ops at a movable point in a block
+
+use crate::link::LinkedList;
+use crate::{Block, Location, Operation, Ptr, Value};
+
+/// Where an `OpBuilder` puts the ops it inserts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InsertPoint {
+    Start,
+    End,
+    Before(Ptr),
+    After(Ptr),
+}
+
+/// Inserts ops into a block at an insertion point, filling in the defs of
+/// their results and recording their uses.
+///
+/// Ops inserted one after the other end up in that order: after inserting at
+/// the start of the block or after an op, the point moves past the new op.
+pub struct OpBuilder<'a> {
+    block: &'a mut Block,
+    point: InsertPoint,
+    location: Location,
+}
+
+impl<'a> OpBuilder<'a> {
+    pub fn new(block: &'a mut Block, point: InsertPoint) -> Self {
+        Self {
+            block,
+            point,
+            location: Location::Unknown,
+        }
+    }
+
+    pub fn at_start(block: &'a mut Block) -> Self {
+        Self::new(block, InsertPoint::Start)
+    }
+
+    pub fn at_end(block: &'a mut Block) -> Self {
+        Self::new(block, InsertPoint::End)
+    }
+
+    pub fn before(block: &'a mut Block, op: Ptr) -> Self {
+        Self::new(block, InsertPoint::Before(op))
+    }
+
+    pub fn after(block: &'a mut Block, op: Ptr) -> Self {
+        Self::new(block, InsertPoint::After(op))
+    }
+
+    pub fn insertion_point(&self) -> InsertPoint {
+        self.point
+    }
+
+    pub fn set_insertion_point(&mut self, point: InsertPoint) {
+        self.point = point;
+    }
+
+    /// Ops inserted without a location of their own get this one
+    pub fn set_location(&mut self, location: Location) {
+        self.location = location;
+    }
+
+    pub fn block(&self) -> &Block {
+        self.block
+    }
+
+    pub fn block_mut(&mut self) -> &mut Block {
+        self.block
+    }
+
+    /// Insert the op at the insertion point
+    pub fn insert(&mut self, mut op: Operation) -> Ptr {
+        if op.location.is_unknown() {
+            op.location = self.location.clone();
+        }
+
+        let ptr = self.block.pool.alloc(op);
+        self.link(ptr);
+        self.block.fill_def(ptr);
+        self.block.add_uses(ptr);
+        ptr
+    }
+
+    /// Insert the op and give back its first result, for ops that have one
+    pub fn build(&mut self, op: Operation) -> Value {
+        let ptr = self.insert(op);
+        self.block.get(ptr).get_result()
+    }
+
+    /// Move an op of the block to the insertion point
+    pub fn move_op(&mut self, op: Ptr) {
+        if let InsertPoint::Before(root) | InsertPoint::After(root) = self.point
+            && root == op
+        {
+            return;
+        }
+
+        self.block.unlink(op);
+        self.link(op);
+    }
+
+    fn link(&mut self, ptr: Ptr) {
+        match self.point {
+            InsertPoint::Start => {
+                match *self.block.head() {
+                    Some(head) => self.block.link_behind(head, ptr),
+                    None => self.block.link_back(ptr),
+                }
+
+                self.point = InsertPoint::After(ptr);
+            }
+            InsertPoint::End => self.block.link_back(ptr),
+            InsertPoint::Before(root) => self.block.link_behind(root, ptr),
+            InsertPoint::After(root) => {
+                self.block.link_ahead(root, ptr);
+                self.point = InsertPoint::After(ptr);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn names(block: &Block) -> Vec<&'static str> {
+        block.iter().map(|op| op.name).collect()
+    }
+
+    #[test]
+    fn insert_in_order_at_each_point() {
+        let mut block = parse(".bb0:\ntest.a\ntest.b").unwrap();
+        let a = Ptr::new(0);
+        let b = Ptr::new(1);
+
+        let mut builder = OpBuilder::at_start(&mut block);
+        builder.insert(Operation::new("test.start1"));
+        builder.insert(Operation::new("test.start2"));
+
+        builder.set_insertion_point(InsertPoint::After(a));
+        builder.insert(Operation::new("test.after1"));
+        builder.insert(Operation::new("test.after2"));
+
+        builder.set_insertion_point(InsertPoint::Before(b));
+        builder.insert(Operation::new("test.before1"));
+        builder.insert(Operation::new("test.before2"));
+
+        builder.set_insertion_point(InsertPoint::End);
+        builder.insert(Operation::new("test.end"));
+
+        assert_eq!(
+            names(&block),
+            [
+                "test.start1",
+                "test.start2",
+                "test.a",
+                "test.after1",
+                "test.after2",
+                "test.before1",
+                "test.before2",
+                "test.b",
+                "test.end",
+            ]
+        );
+    }
+
+    #[test]
+    fn insert_into_empty_block() {
+        let mut block = Block::new();
+
+        OpBuilder::at_start(&mut block).insert(Operation::new("test.a"));
+        assert_eq!(names(&block), ["test.a"]);
+
+        let mut block = Block::new();
+        OpBuilder::at_end(&mut block).insert(Operation::new("test.a"));
+        assert_eq!(names(&block), ["test.a"]);
+    }
+
+    #[test]
+    fn fill_defs_uses_and_locations() {
+        let mut block = parse(".bb0:\n%0 := test.def\ntest.use %0").unwrap();
+        let def = block.get(Ptr::new(0)).get_result();
+
+        let mut builder = OpBuilder::after(&mut block, Ptr::new(0));
+        builder.set_location(Location::span("a.c", 1, 1, 1));
+
+        let mut copy = Operation::new("test.copy");
+        copy.operands = vec![def];
+        copy.results = vec![Value::new(None)];
+        let result = builder.build(copy);
+        let ptr = result.def.expect("the builder fills in defs");
+
+        assert_eq!(block.get(ptr).location, Location::span("a.c", 1, 1, 1));
+        assert_eq!(block.users(def).collect::<Vec<_>>(), [Ptr::new(1), ptr]);
+    }
+
+    #[test]
+    fn move_ops_around() {
+        let mut block = parse(".bb0:\ntest.a\ntest.b\ntest.c").unwrap();
+        let (a, c) = (Ptr::new(0), Ptr::new(2));
+
+        let mut builder = OpBuilder::at_start(&mut block);
+        builder.move_op(c);
+        builder.set_insertion_point(InsertPoint::End);
+        builder.move_op(a);
+        assert_eq!(names(&block), ["test.c", "test.b", "test.a"]);
+
+        let mut builder = OpBuilder::before(&mut block, a);
+        builder.move_op(a);
+        assert_eq!(names(&block), ["test.c", "test.b", "test.a"]);
+    }
+}
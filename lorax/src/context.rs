@@ -0,0 +1,121 @@
+// Allocation of the ids identifying values and blocks
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Owns the counters that values and blocks get their ids from.
+///
+/// Each thread has a context of its own, which `Value::new` and `Block::new`
+/// draw from. `enter` swaps in another one, e.g. to start a compilation, or a
+/// test, from id 0. Values and blocks made under different contexts can share
+/// ids, so each one is tagged with the id of its context: values from
+/// different contexts are never equal, and the verifier reports IR that mixes
+/// them.
+#[derive(Debug)]
+pub struct Context {
+    id: usize,
+    next_value: usize,
+    next_block: usize,
+}
+
+static NEXT_CONTEXT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static CURRENT: RefCell<Context> = RefCell::new(Context::new());
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_CONTEXT.fetch_add(1, Ordering::Relaxed),
+            next_value: 0,
+            next_block: 0,
+        }
+    }
+
+    /// Tells this context apart from every other one in the process
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The id of the current thread's context
+    pub(crate) fn current_id() -> usize {
+        CURRENT.with_borrow(|ctx| ctx.id)
+    }
+
+    /// Run `f` with this as the current thread's context, so the ids of values
+    /// and blocks made in `f` come from it
+    pub fn enter<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        // swapped back even if `f` panics
+        struct Restore<'a>(&'a mut Context);
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                CURRENT.with_borrow_mut(|current| std::mem::swap(current, self.0));
+            }
+        }
+
+        CURRENT.with_borrow_mut(|current| std::mem::swap(current, self));
+        let _restore = Restore(self);
+        f()
+    }
+
+    pub(crate) fn next_value_id() -> usize {
+        CURRENT.with_borrow_mut(|ctx| {
+            ctx.next_value += 1;
+            ctx.next_value - 1
+        })
+    }
+
+    pub(crate) fn next_block_id() -> usize {
+        CURRENT.with_borrow_mut(|ctx| {
+            ctx.next_block += 1;
+            ctx.next_block - 1
+        })
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Block, Value};
+
+    #[test]
+    fn entered_contexts_count_from_zero() {
+        let ids = || {
+            let (a, b) = (Value::new(None), Value::new(None));
+            (a == b, Block::new().id(), Block::new().id())
+        };
+
+        let first = Context::new().enter(ids);
+        assert_eq!(first, (false, 0, 1));
+        assert_eq!(Context::new().enter(ids), first);
+
+        // ids made in a context carry on where it left off
+        let mut ctx = Context::new();
+        ctx.enter(ids);
+        assert_eq!(ctx.enter(|| Block::new().id()), 2);
+    }
+
+    #[test]
+    fn tag_ids_with_their_context() {
+        let (mut a, mut b) = (Context::new(), Context::new());
+        assert_ne!(a.id(), b.id());
+
+        let (val_a, block_a) = a.enter(|| (Value::new(None), Block::new()));
+        let (val_b, block_b) = b.enter(|| (Value::new(None), Block::new()));
+
+        // the same ids, but not the same values
+        assert_eq!(val_a.to_string(), val_b.to_string());
+        assert_ne!(val_a, val_b);
+        assert_eq!(val_a.context(), a.id());
+        assert_eq!(block_a.id(), block_b.id());
+        assert_ne!(block_a.context(), block_b.context());
+    }
+}
@@ -0,0 +1,295 @@
+// Records which dialects and ops exist, and what each op promises
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::Operation;
+use crate::attr::AttrKind;
+use crate::types::{Type, TypeConstraint};
+
+/// A property of an op that passes can rely on without knowing the op itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpTrait {
+    /// No side effects, so the op can be removed if unused or merged with an identical one
+    Pure,
+    /// The op must be the last one in its block
+    Terminator,
+    /// The order of the operands doesn't matter
+    Commutative,
+    /// The op does something besides producing its result, like writing memory or a register
+    HasSideEffects,
+    /// Blocks nested in the op can't use values defined outside of it
+    IsolatedFromAbove,
+    /// The ops in the op's blocks are symbols, looked up by name from inside it
+    SymbolTable,
+}
+
+/// What every instance of an op looks like. `def_op!` generates one per op.
+#[derive(Debug)]
+pub struct OpDefinition {
+    pub name: &'static str,
+    pub operands: usize,
+    pub operand_names: &'static [&'static str],
+    pub results: usize,
+    /// `["result"]` for an op with a single result
+    pub result_names: &'static [&'static str],
+    pub blocks: usize,
+    /// How many blocks the op branches to
+    pub successors: usize,
+    pub attributes: &'static [(&'static str, AttrKind)],
+    /// Types the operands and results, by name, must have
+    pub types: &'static [(&'static str, TypeConstraint)],
+    pub traits: &'static [OpTrait],
+}
+
+impl OpDefinition {
+    pub fn has_trait(&self, t: OpTrait) -> bool {
+        self.traits.contains(&t)
+    }
+
+    /// The constraint on the type of the operand or result named `name`
+    pub fn type_of(&self, name: &str) -> TypeConstraint {
+        self.types
+            .iter()
+            .find(|&&(n, _)| n == name)
+            .map_or(TypeConstraint::Any, |&(_, constraint)| constraint)
+    }
+
+    /// The type the result named `name` must have in `op`, if the constraint
+    /// pins it down
+    pub fn result_type(&self, name: &str, op: &Operation) -> Option<Type> {
+        match self.type_of(name) {
+            TypeConstraint::Exact(name) => name.parse().ok(),
+            TypeConstraint::SameAs(other) => {
+                match self.operand_names.iter().position(|&n| n == other) {
+                    Some(idx) => op.operands.get(idx)?.ty(),
+                    None => op.attributes.get(other)?.int_type(),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Give a freshly built op's untyped results the types its definition implies
+    pub fn infer_result_types(&self, mut op: Operation) -> Operation {
+        let types: Vec<_> = self
+            .result_names
+            .iter()
+            .map(|name| self.result_type(name, &op))
+            .collect();
+
+        for (result, ty) in op.results.iter_mut().zip(types) {
+            if result.ty.is_none() {
+                result.ty = ty;
+            }
+        }
+
+        op
+    }
+}
+
+/// A named group of op definitions.
+#[derive(Debug)]
+pub struct Dialect {
+    name: &'static str,
+    ops: Vec<&'static OpDefinition>,
+}
+
+impl Dialect {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            ops: Vec::new(),
+        }
+    }
+
+    pub fn op(mut self, definition: &'static OpDefinition) -> Self {
+        self.ops.push(definition);
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn ops(&self) -> &[&'static OpDefinition] {
+        &self.ops
+    }
+
+    /// Make the dialect's ops known to `lookup` and `Operation::definition`
+    pub fn register(&self) {
+        let mut registry = REGISTRY.write().expect("op registry was poisoned");
+
+        for &definition in &self.ops {
+            registry.insert(definition.name, definition);
+        }
+    }
+}
+
+static REGISTRY: RwLock<BTreeMap<&'static str, &'static OpDefinition>> =
+    RwLock::new(BTreeMap::new());
+
+/// The definition of a registered op
+pub fn lookup(name: &str) -> Option<&'static OpDefinition> {
+    REGISTRY
+        .read()
+        .expect("op registry was poisoned")
+        .get(name)
+        .copied()
+}
+
+impl Operation {
+    /// This op's definition, if its dialect was registered
+    pub fn definition(&self) -> Option<&'static OpDefinition> {
+        lookup(self.name)
+    }
+
+    /// Whether this op is registered with the trait. Unregistered ops have no traits.
+    pub fn has_trait(&self, t: OpTrait) -> bool {
+        self.definition().is_some_and(|def| def.has_trait(t))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::attr::{Attribute, SymbolRef};
+    use crate::{Block, Value, def_op};
+
+    def_op! {
+        reg.konst() {
+            value: u32
+        } [Pure]
+    }
+
+    def_op! {
+        reg.add(lhs: Value, rhs: Value) [Pure, Commutative]
+    }
+
+    def_op! {
+        reg.ret(val: Value) -> None [Terminator]
+    }
+
+    def_op! {
+        reg.func(body: Block) [IsolatedFromAbove]
+    }
+
+    def_op! {
+        reg.neg(val: Value) where val: Int, result: SameAs(val) [Pure]
+    }
+
+    def_op! {
+        reg.flags() where result: Exact("!reg.flags")
+    }
+
+    def_op! {
+        reg.divmod(lhs: Value, rhs: Value) -> (quot, rem)
+            where lhs: Int, quot: SameAs(lhs), rem: SameAs(lhs) [Pure]
+    }
+
+    def_op! {
+        reg.call(arg: Value) { callee: SymbolRef, align: u8, pure: bool } -> None
+    }
+
+    fn dialect() -> Dialect {
+        Dialect::new("reg")
+            .op(&konst::DEFINITION)
+            .op(&add::DEFINITION)
+            .op(&ret::DEFINITION)
+            .op(&func::DEFINITION)
+    }
+
+    #[test]
+    fn definitions_describe_ops() {
+        assert_eq!(konst(1).name, konst::DEFINITION.name);
+        assert_eq!(konst::DEFINITION.name, "reg.konst");
+        assert_eq!(konst::DEFINITION.attributes, &[("value", AttrKind::Int)]);
+        assert_eq!(konst::DEFINITION.results, 1);
+        assert_eq!(konst::value(&konst(7)), Some(7));
+
+        assert_eq!(add::DEFINITION.operands, 2);
+        assert!(add::DEFINITION.has_trait(OpTrait::Commutative));
+
+        assert_eq!(ret::DEFINITION.results, 0);
+        assert_eq!(func(Block::new()).blocks.len(), func::DEFINITION.blocks);
+        assert_eq!(dialect().ops().len(), 4);
+    }
+
+    #[test]
+    fn constructors_infer_result_types() {
+        assert_eq!(
+            neg::DEFINITION.types,
+            &[
+                ("val", TypeConstraint::Int),
+                ("result", TypeConstraint::SameAs("val"))
+            ]
+        );
+        assert_eq!(neg::DEFINITION.type_of("other"), TypeConstraint::Any);
+
+        let i8 = Type::int(8, true);
+        let op = neg(Value::typed(None, i8));
+        assert_eq!(op.get_result().ty(), Some(i8));
+        assert_eq!(neg(Value::new(None)).get_result().ty(), None);
+
+        assert_eq!(
+            flags().get_result().ty(),
+            Some(Type::opaque("reg", "flags"))
+        );
+
+        // results the definition leaves open can be typed after building
+        let konst = konst(1).with_type(i8);
+        assert_eq!(konst.get_result().ty(), Some(i8));
+        assert_eq!(konst.to_string(), "%0: i8 := reg.konst {value = 1 : u32}");
+    }
+
+    #[test]
+    fn declare_multiple_results() {
+        assert_eq!(divmod::DEFINITION.results, 2);
+        assert_eq!(divmod::DEFINITION.result_names, &["quot", "rem"]);
+        assert_eq!(konst::DEFINITION.result_names, &["result"]);
+        assert!(ret::DEFINITION.result_names.is_empty());
+
+        let i32 = Type::int(32, true);
+        let op = divmod(Value::typed(None, i32), Value::new(None));
+        assert_eq!(op.num_results(), 2);
+        assert_ne!(op.result(0), op.result(1));
+        assert_eq!(op.result(1).ty(), Some(i32));
+
+        assert_eq!(op.to_string(), "%0: i32, %1: i32 := reg.divmod %2, %3");
+    }
+
+    #[test]
+    fn declare_named_attributes() {
+        assert_eq!(
+            call::DEFINITION.attributes,
+            &[
+                ("callee", AttrKind::Symbol),
+                ("align", AttrKind::Int),
+                ("pure", AttrKind::Bool)
+            ]
+        );
+
+        let mut op = call(Value::new(None), SymbolRef("main".to_owned()), 4, false);
+        assert_eq!(call::callee(&op), Some(SymbolRef("main".to_owned())));
+        assert_eq!(call::align(&op), Some(4));
+        assert_eq!(call::pure(&op), Some(false));
+
+        // an accessor only finds the kind of value it was declared with
+        op.add_attr("align".to_owned(), Attribute::Str("4".to_owned()));
+        assert_eq!(call::align(&op), None);
+    }
+
+    #[test]
+    fn query_registered_ops() {
+        let op = add(Value::new(None), Value::new(None));
+        assert!(op.definition().is_none());
+        assert!(!op.has_trait(OpTrait::Pure));
+
+        dialect().register();
+
+        assert_eq!(op.definition().unwrap().name, "reg.add");
+        assert!(op.has_trait(OpTrait::Pure));
+        assert!(!op.has_trait(OpTrait::Terminator));
+        assert!(ret(Value::new(None)).has_trait(OpTrait::Terminator));
+    }
+}
@@ -1,42 +1,106 @@
-use std::{fmt::Display, sync::atomic};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
 use crate::attr::{Attribute, AttributeMap};
 use crate::link::{LinkedList, LinkedNode};
-use crate::pool::{Pool, Ptr};
+use crate::parse::intern;
+use crate::pool::{Pool, Ptr, Remap};
+use crate::types::Type;
+use crate::{Context, Location};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Value {
     id: usize,
+    context: usize,
     pub(crate) def: Option<Ptr>,
+    pub(crate) ty: Option<Type>,
+    name: Option<&'static str>,
 }
 
 impl Value {
+    /// A new untyped value
     pub fn new(ptr: Option<Ptr>) -> Self {
-        static TMP_ID_COUNTER: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
-
         Self {
-            id: TMP_ID_COUNTER.fetch_add(1, atomic::Ordering::Relaxed),
+            id: Context::next_value_id(),
+            context: Context::current_id(),
             def: ptr,
+            ty: None,
+            name: None,
         }
     }
+
+    pub fn typed(ptr: Option<Ptr>, ty: Type) -> Self {
+        Self {
+            ty: Some(ty),
+            ..Self::new(ptr)
+        }
+    }
+
+    pub fn ty(&self) -> Option<Type> {
+        self.ty
+    }
+
+    /// Print the value as `%name`, unless something printed before it took
+    /// the name. Copies taken before keep printing as a number.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(intern(name));
+        self
+    }
+
+    pub fn name_hint(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// The id of the context the value was made under
+    pub fn context(&self) -> usize {
+        self.context
+    }
 }
 
+// values are identified by their id and context alone, copies taken before
+// and after the def was filled in still refer to the same value
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.context == other.context
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.context.hash(state);
+    }
+}
+
+// by id, which only identifies it; printed in a block, it gets a stable name
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "%{}", self.id)
     }
 }
 
-pub type OpResult = Option<Value>;
+/// A block an op can branch to, and the values it passes as the block's arguments.
+///
+/// The block is named by its id, and is a sibling of the block holding the op.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Successor {
+    pub block: usize,
+    pub args: Vec<Value>,
+}
 
 #[derive(Debug)]
 pub struct Operation {
     pub name: &'static str,
     pub operands: Vec<Value>,
+    pub successors: Vec<Successor>,
     pub blocks: Vec<Block>,
-    pub result: OpResult,
+    pub results: Vec<Value>,
 
     pub attributes: AttributeMap,
+    pub location: Location,
 
     pub behind: Option<Ptr>,
     pub ahead: Option<Ptr>,
@@ -65,17 +129,29 @@ impl Operation {
         self.blocks.push(block);
     }
 
+    /// The first result
     pub fn get_result(&self) -> Value {
-        self.result
-            .expect("this should be called on an op with at least one result")
+        self.result(0)
     }
 
     pub fn get_mut_result(&mut self) -> &mut Value {
-        self.result
-            .as_mut()
+        self.results
+            .first_mut()
             .expect("this should be called on an op with at least one result")
     }
 
+    /// The result at `idx`
+    pub fn result(&self, idx: usize) -> Value {
+        *self
+            .results
+            .get(idx)
+            .expect("this should be called with the index of one of the op's results")
+    }
+
+    pub fn num_results(&self) -> usize {
+        self.results.len()
+    }
+
     pub fn walk_blocks(&self) -> impl Iterator<Item = &Block> {
         self.blocks.iter()
     }
@@ -87,190 +163,642 @@ impl Operation {
     pub fn add_attr(&mut self, key: String, attr: Attribute) {
         self.attributes.insert(key, attr);
     }
+
+    /// Set where the op came from
+    pub fn at(mut self, location: Location) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// Add a block the op can branch to, passing `args` to it
+    pub fn with_successor(mut self, block: &Block, args: Vec<Value>) -> Self {
+        self.successors.push(Successor {
+            block: block.id,
+            args,
+        });
+        self
+    }
+
+    /// The operands, followed by the arguments passed to each successor.
+    /// `Use::operand` indexes into this.
+    pub fn all_operands(&self) -> impl Iterator<Item = &Value> {
+        self.operands
+            .iter()
+            .chain(self.successors.iter().flat_map(|s| &s.args))
+    }
+
+    pub fn all_operands_mut(&mut self) -> impl Iterator<Item = &mut Value> {
+        self.operands
+            .iter_mut()
+            .chain(self.successors.iter_mut().flat_map(|s| &mut s.args))
+    }
+
+    /// Give the op's first result a type. Only meant for ops that were just built,
+    /// copies of the result taken before keep their old type.
+    pub fn with_type(mut self, ty: Type) -> Self {
+        self.get_mut_result().ty = Some(ty);
+        self
+    }
 }
 
+#[cfg(test)]
+impl Operation {
+    /// An op with nothing but a name, for tests to fill in the rest of
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            operands: Vec::new(),
+            successors: Vec::new(),
+            blocks: Vec::new(),
+            results: Vec::new(),
+            attributes: AttributeMap::new(),
+            location: Location::Unknown,
+            behind: None,
+            ahead: None,
+        }
+    }
+}
+
+/// Define an op: a constructor function named after it, and a module of the
+/// same name holding its `DEFINITION` for registering with a `Dialect`.
+///
+/// Attributes are declared in braces after the operands, e.g.
+/// `{ value: i64, name: String }`. The constructor takes them after the
+/// operands, and the module gets an accessor for each one.
+///
+/// The op has one result unless it says otherwise: `-> None` for no result,
+/// `-> dst` to make the operand `dst` the result, and `-> (quot, rem)` for
+/// several results, named so they can be constrained.
+///
+/// An op that branches says to how many blocks with `successors`, e.g.
+/// `-> None successors 2`. The constructor doesn't take them, they're added
+/// with `Operation::with_successor`.
+///
+/// Types the operands and results must have follow `where`, e.g.
+/// `where val: Int, result: SameAs(val)`, where a single result is called
+/// `result`. Results whose type follows from them are typed by the constructor.
+///
+/// Traits the op has are listed in brackets at the end, e.g. `[Pure, Commutative]`.
 #[macro_export]
 macro_rules! def_op {
     // Block-only operation (no operands, no result)
-    ($dl:ident . $name:ident ($field:ident : Block)) => {
-        pub fn $name($field: Block) -> Operation {
-            use ::lorax::attr::AttributeMap;
+    ($dl:ident . $name:ident ($field:ident : Block)
+        $({ $($attr:ident : $attr_ty:ty),* $(,)? })?
+        $([$($tr:ident),* $(,)?])?
+    ) => {
+        pub fn $name($field: Block $($(, $attr: $attr_ty)*)?) -> Operation {
+            #[allow(unused_mut)]
+            let mut attributes = $crate::attr::AttributeMap::new();
+            $($(
+                attributes.insert(
+                    stringify!($attr).to_owned(),
+                    $crate::attr::AttrValue::into_attr($attr),
+                );
+            )*)?
+
             Operation {
                 name: stringify!($dl . $name),
                 operands: Vec::new(),
+                successors: Vec::new(),
                 blocks: vec![$field],
-                result: None,
+                results: Vec::new(),
 
-                attributes: AttributeMap::new(),
+                attributes,
+                location: $crate::Location::Unknown,
 
                 behind: None,
                 ahead: None,
             }
         }
-    };
 
-    // Operation with operands, optional result
-    ($dl:ident . $name:ident ( $($field:ident : $ty:ty),* $(,)? ) $(-> $ret:ident)? ) => {
-        pub fn $name($($field: $ty),*) -> Operation {
-            use ::lorax::attr::AttributeMap;
+        $crate::def_op!(
+            @def $dl.$name,
+            [],
+            [],
+            1,
+            0,
+            [$($(($attr, $attr_ty)),*)?],
+            [],
+            [$($($tr),*)?]
+        );
+    };
 
-            Operation {
+    // Operation with operands and attributes, optional result
+    ($dl:ident . $name:ident ( $($field:ident : $ty:ty),* $(,)? )
+        $({ $($attr:ident : $attr_ty:ty),* $(,)? })?
+        $(-> $ret:tt)?
+        $(successors $succ:literal)?
+        $(where $($tyname:ident : $constraint:ident $(($arg:tt))?),* $(,)?)?
+        $([$($tr:ident),* $(,)?])?
+    ) => {
+        pub fn $name($($field: $ty,)* $($($attr: $attr_ty),*)?) -> Operation {
+            #[allow(unused_mut)]
+            let mut attributes = $crate::attr::AttributeMap::new();
+            $($(
+                attributes.insert(
+                    stringify!($attr).to_owned(),
+                    $crate::attr::AttrValue::into_attr($attr),
+                );
+            )*)?
+
+            $name::DEFINITION.infer_result_types(Operation {
                 name: stringify!($dl . $name),
                 operands: vec![$($field.into()),*],
+                successors: Vec::new(),
                 blocks: Vec::new(),
-                result: def_op!(@ret $( $ret )?),
+                results: $crate::def_op!(@ret $( $ret )?),
 
-                attributes: AttributeMap::new(),
+                attributes,
+                location: $crate::Location::Unknown,
 
                 behind: None,
                 ahead: None,
-            }
+            })
         }
-    };
 
-    // Operation with one attribute
-    ($dl:ident . $name:ident (  ) { value: $ty:ty }) => {
-        pub fn $name(value: $ty) -> Operation {
-            use ::lorax::attr::{AttributeMap, Attribute};
-
-            let mut attributes = AttributeMap::new();
-            attributes.insert("value".to_owned(), Attribute::Int(value));
+        $crate::def_op!(
+            @def $dl.$name,
+            [$(stringify!($field)),*],
+            $crate::def_op!(@results $( $ret )?),
+            0,
+            $crate::def_op!(@successors $($succ)?),
+            [$($(($attr, $attr_ty)),*)?],
+            [$($(($tyname, $constraint $(($arg))?)),*)?],
+            [$($($tr),*)?]
+        );
+    };
 
-            Operation {
+    // Definition, in a module named after the op
+    (@def $dl:ident . $name:ident, [$($operand:expr),*], $results:expr, $blocks:expr,
+        $successors:expr, [$(($attr:ident, $attr_ty:ty)),*],
+        [$(($tyname:ident, $constraint:ident $(($arg:tt))?)),*], [$($tr:ident),*]
+    ) => {
+        pub mod $name {
+            // attribute types are named as they are next to the op
+            #[allow(unused_imports)]
+            use super::*;
+
+            pub static DEFINITION: $crate::dialect::OpDefinition = $crate::dialect::OpDefinition {
                 name: stringify!($dl . $name),
-                operands: Vec::new(),
-                blocks: Vec::new(),
-                result: Some(Value::new(None)),
-
-                attributes: attributes,
-
-                behind: None,
-                ahead: None,
-            }
+                operands: <[&str]>::len(&[$($operand),*]),
+                operand_names: &[$($operand),*],
+                results: <[&str]>::len(&$results),
+                result_names: &$results,
+                blocks: $blocks,
+                successors: $successors,
+                attributes: &[$((
+                    stringify!($attr),
+                    <$attr_ty as $crate::attr::AttrValue>::KIND
+                )),*],
+                types: &[$((
+                    stringify!($tyname),
+                    $crate::types::TypeConstraint::$constraint $(($crate::def_op!(@arg $arg)))?
+                )),*],
+                traits: &[$($crate::dialect::OpTrait::$tr),*],
+            };
+
+            $(
+                /// The attribute, if the op has it and it holds the right kind of value
+                pub fn $attr(op: &$crate::Operation) -> Option<$attr_ty> {
+                    op.attributes
+                        .get(stringify!($attr))
+                        .and_then(<$attr_ty as $crate::attr::AttrValue>::from_attr)
+                }
+            )*
         }
     };
 
-    // Attribute map
-    (@attr) => {};
+    // Constraint arguments, operands are named by identifiers
+    (@arg $arg:literal) => { $arg };
+    (@arg $arg:ident) => { stringify!($arg) };
 
-    // Result handling
-    (@ret) => { Some(Value::new(None)) };
-    (@ret None) => { None };
-    (@ret Value) => { Some(Value::new()) };
-    (@ret $ret:ident) => { Some(($ret).into()) };
-}
+    (@successors) => { 0 };
+    (@successors $succ:literal) => { $succ };
 
-fn fmt_delimited_list<I>(list: &mut I, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
-where
-    I: Iterator,
-    I::Item: Display,
-{
-    if let Some(item) = list.next() {
-        write!(f, "{}", item)?;
-    }
+    // Result handling
+    (@ret) => { vec![Value::new(None)] };
+    (@ret None) => { Vec::new() };
+    (@ret $ret:ident) => { vec![($ret).into()] };
+    (@ret ($($ret:ident),* $(,)?)) => { vec![$($crate::def_op!(@fresh $ret)),*] };
 
-    for item in list {
-        write!(f, ", {}", item)?;
-    }
+    (@fresh $ret:ident) => { Value::new(None) };
 
-    Ok(())
+    // Result names
+    (@results None) => { [] };
+    (@results ($($ret:ident),* $(,)?)) => { [$(stringify!($ret)),*] };
+    (@results $($ret:ident)?) => { ["result"] };
 }
 
-impl Display for Operation {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(var) = self.result {
-            write!(f, "{} := {} ", var, self.name)?;
-        } else {
-            write!(f, "{} ", self.name)?;
-        }
-
-        fmt_delimited_list(&mut self.operands.iter(), f)?;
-
-        if !self.attributes.is_empty() {
-            write!(f, "{:?}", self.attributes)?;
-        }
-
-        if !self.blocks.is_empty() {
-            write!(f, "\n")?;
-        }
-
-        for block in &self.blocks {
-            write!(f, "{}", block)?;
-        }
-
-        Ok(())
-    }
+/// An operand of an op that refers to a value, counting successor arguments
+/// after the operands, as in `Operation::all_operands`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Use {
+    pub op: Ptr,
+    pub operand: usize,
 }
 
 #[derive(Debug)]
 pub struct Block {
     pub(crate) id: usize,
+    context: usize,
     pub pool: Pool<Operation>,
 
+    // values passed in by the ops branching here, defined by no op
+    pub(crate) args: Vec<Value>,
+
     head: Option<Ptr>,
     tail: Option<Ptr>,
+
+    // uses of values by ops in this block, not counting nested blocks
+    uses: HashMap<Value, Vec<Use>>,
 }
 
 impl Block {
     pub(crate) fn unique_id() -> usize {
-        static BLOCK_ID_COUNTER: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
-        BLOCK_ID_COUNTER.fetch_add(1, atomic::Ordering::Relaxed)
+        Context::next_block_id()
     }
 
     pub fn new() -> Self {
         Self {
             id: Self::unique_id(),
+            context: Context::current_id(),
             pool: Pool::new(),
 
+            args: Vec::new(),
+
             head: None,
             tail: None,
+
+            uses: HashMap::new(),
         }
     }
 
+    /// A block with an id that was reserved with `unique_id`, e.g. by a branch
+    /// parsed before the block it targets
+    pub(crate) fn with_id(id: usize) -> Self {
+        Self { id, ..Self::new() }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The id of the context the block was made under
+    pub fn context(&self) -> usize {
+        self.context
+    }
+
+    pub fn args(&self) -> &[Value] {
+        &self.args
+    }
+
+    /// Add an argument of type `ty` to the block
+    pub fn add_arg(&mut self, ty: Option<Type>) -> Value {
+        let mut arg = Value::new(None);
+        arg.ty = ty;
+        self.args.push(arg);
+        arg
+    }
+
     pub fn get(&self, ptr: Ptr) -> &Operation {
+        if cfg!(debug_assertions) && self.pool.get(ptr).is_none() {
+            self.dangling(ptr);
+        }
+
         self.pool.deref(ptr)
     }
 
     pub fn get_mut(&mut self, ptr: Ptr) -> &mut Operation {
+        if cfg!(debug_assertions) && self.pool.get(ptr).is_none() {
+            self.dangling(ptr);
+        }
+
         self.pool.deref_mut(ptr)
     }
 
+    /// Report a bad `ptr` into this block, naming the op that took over its slot if any
+    #[cold]
+    fn dangling(&self, ptr: Ptr) -> ! {
+        let why = self.pool.why_dangling(ptr);
+
+        match self.pool.occupant(ptr) {
+            Some(op) => panic!(
+                "Deref of dangling ptr in .bb{}: {}, the slot now holds '{}'",
+                self.id, why, op.name
+            ),
+            None => panic!("Deref of dangling ptr in .bb{}: {}", self.id, why),
+        }
+    }
+
+    /// The ops of the block, in order
     pub fn walk_ops(&self) -> impl Iterator<Item = &Operation> {
-        self.pool.iter()
+        self.iter()
     }
 
+    /// The ops of the block, in order, allowing changes to them but not to their links
     pub fn walk_ops_mut(&mut self) -> impl Iterator<Item = &mut Operation> {
-        self.pool.iter_mut()
+        let position = self.list_positions();
+        let positions: Vec<_> = self.pool.ptrs().map(|ptr| position[ptr.idx]).collect();
+        let mut ops: Vec<_> = positions
+            .into_iter()
+            .zip(self.pool.iter_mut())
+            .filter_map(|(position, op)| Some((position?, op)))
+            .collect();
+
+        ops.sort_by_key(|&(position, _)| position);
+        ops.into_iter().map(|(_, op)| op)
+    }
+
+    /// Pointers to the ops of the block, in order
+    pub fn ptrs(&self) -> impl Iterator<Item = Ptr> + '_ {
+        std::iter::successors(self.head, |&ptr| self.get(ptr).ahead)
     }
 
+    /// Append an operation, filling in the op's result with a def
     pub fn push(&mut self, op: Operation) -> Ptr {
-        LinkedList::push(self, op)
+        let ptr = LinkedList::push(self, op);
+        self.fill_def(ptr);
+        self.add_uses(ptr);
+        ptr
+    }
+
+    /// Remove an op from the block and free its slot.
+    ///
+    /// Uses of its result should be replaced beforehand, they aren't updated.
+    pub fn erase_op(&mut self, ptr: Ptr) -> Operation {
+        self.unlink(ptr);
+        self.remove_uses(ptr);
+        self.pool.free(ptr)
+    }
+
+    /// Move the ops from `first` through `last` out of `from`, in front of
+    /// `before` or at the end of this block, returning where they are now.
+    ///
+    /// Uses are moved with them, and mentions of their results in this block
+    /// point at their new defs. Mentions left in `from` are left without a
+    /// def, since a ptr into this block could be taken for one of its own ops.
+    pub fn splice(
+        &mut self,
+        before: Option<Ptr>,
+        from: &mut Block,
+        first: Ptr,
+        last: Ptr,
+    ) -> Vec<Ptr> {
+        let old: Vec<_> = std::iter::successors(Some(first), |&ptr| {
+            (ptr != last).then(|| from.get(ptr).ahead).flatten()
+        })
+        .collect();
+
+        for &ptr in &old {
+            from.remove_uses(ptr);
+        }
+
+        let moved = LinkedList::splice(self, before, from, first, last);
+        let mut defs = HashMap::new();
+
+        for (&old, &new) in old.iter().zip(&moved) {
+            for &val in &self.get(new).results {
+                if val.def == Some(old) {
+                    defs.insert(val, Some(new));
+                }
+            }
+
+            self.add_uses(new);
+        }
+
+        self.redefine(&defs);
+
+        defs.values_mut().for_each(|def| *def = None);
+        from.redefine(&defs);
+
+        moved
+    }
+
+    /// Record the operands of the op at `ptr` as uses
+    pub(crate) fn add_uses(&mut self, ptr: Ptr) {
+        for (operand, &val) in self.pool.deref(ptr).all_operands().enumerate() {
+            self.uses
+                .entry(val)
+                .or_default()
+                .push(Use { op: ptr, operand });
+        }
+    }
+
+    /// Forget the uses made by the op at `ptr`, before it's replaced or removed
+    pub(crate) fn remove_uses(&mut self, ptr: Ptr) {
+        for val in self.pool.deref(ptr).all_operands() {
+            if let Some(uses) = self.uses.get_mut(val) {
+                uses.retain(|u| u.op != ptr);
+
+                if uses.is_empty() {
+                    self.uses.remove(val);
+                }
+            }
+        }
+    }
+
+    /// Every operand in this block that refers to `val`, not counting blocks
+    /// nested in it or the other blocks of its region.
+    ///
+    /// Uses are tracked by `push` and the rewriting methods, changing an op's
+    /// operands directly through `get_mut` isn't seen.
+    pub fn uses(&self, val: Value) -> &[Use] {
+        self.uses.get(&val).map_or(&[], Vec::as_slice)
+    }
+
+    /// The ops in this block using `val`, once per use
+    pub fn users(&self, val: Value) -> impl Iterator<Item = Ptr> + '_ {
+        self.uses(val).iter().map(|u| u.op)
+    }
+
+    /// How many uses `val` has in this block and the blocks nested in it
+    pub fn num_uses(&self, val: Value) -> usize {
+        let nested: usize = self
+            .pool
+            .iter()
+            .flat_map(Operation::walk_blocks)
+            .map(|block| block.num_uses(val))
+            .sum();
+
+        self.uses(val).len() + nested
+    }
+
+    /// Whether `val` is used once in this block and the blocks nested in it.
+    /// Uses in the other blocks of its region aren't seen, see `num_uses_in`.
+    pub fn has_one_use(&self, val: Value) -> bool {
+        self.num_uses(val) == 1
+    }
+
+    /// How many uses `val` has in the blocks of a region, e.g. of an op.
+    /// A value can be used in other blocks of the region than its own.
+    pub fn num_uses_in(region: &[Block], val: Value) -> usize {
+        region.iter().map(|block| block.num_uses(val)).sum()
+    }
+
+    /// Make every use of `old` in the blocks of a region, and any nested in
+    /// them, refer to `new` instead
+    pub fn replace_all_uses_in(region: &mut [Block], old: Value, new: Value) {
+        for block in region {
+            block.replace_all_uses_with(old, new);
+        }
+    }
+
+    /// Make every use of `old` in this block, and any nested in it, refer to `new` instead.
+    /// Uses in the other blocks of its region aren't seen, see `replace_all_uses_in`.
+    pub fn replace_all_uses_with(&mut self, old: Value, new: Value) {
+        if old == new {
+            return;
+        }
+
+        for u in self.uses.remove(&old).unwrap_or_default() {
+            let op = self.pool.deref_mut(u.op);
+            *op.all_operands_mut()
+                .nth(u.operand)
+                .expect("uses should index the op's operands") = new;
+            self.uses.entry(new).or_default().push(u);
+        }
+
+        for op in self.pool.iter_mut() {
+            for block in op.walk_blocks_mut() {
+                block.replace_all_uses_with(old, new);
+            }
+        }
+    }
+
+    /// Point the results of the op at `ptr` back to it, unless they're already defined elsewhere
+    pub(crate) fn fill_def(&mut self, ptr: Ptr) {
+        for val in &mut self.get_mut(ptr).results {
+            if val.def.is_none() {
+                val.def = Some(ptr);
+            }
+        }
+    }
+
+    /// Drop the slots of erased ops from the pool.
+    ///
+    /// Links, uses and the defs of values produced here, including uses of them
+    /// in nested blocks, are updated. Any other `Ptr` into the block is
+    /// invalidated and can be updated with the returned `Remap`.
+    pub fn compact(&mut self) -> Remap {
+        let remap = self.pool.compact();
+
+        remap.apply_opt(&mut self.head);
+        remap.apply_opt(&mut self.tail);
+
+        let mut defs = HashMap::new();
+
+        for op in self.pool.iter_mut() {
+            remap.apply_opt(&mut op.behind);
+            remap.apply_opt(&mut op.ahead);
+
+            for &val in &op.results {
+                if let Some(def) = val.def.and_then(|def| remap.get(def)) {
+                    defs.insert(val, Some(def));
+                }
+            }
+        }
+
+        self.redefine(&defs);
+
+        self.uses.clear();
+        for ptr in self.pool.ptrs().collect::<Vec<_>>() {
+            self.add_uses(ptr);
+        }
+
+        remap
+    }
+
+    /// Point every mention of a value in `defs`, here or nested, at its new
+    /// def, or at none
+    pub(crate) fn redefine(&mut self, defs: &HashMap<Value, Option<Ptr>>) {
+        let redefine = |val: &mut Value| {
+            if let Some(&def) = defs.get(val) {
+                val.def = def;
+            }
+        };
+
+        for op in self.pool.iter_mut() {
+            op.all_operands_mut().for_each(redefine);
+            op.results.iter_mut().for_each(redefine);
+
+            for block in op.walk_blocks_mut() {
+                block.redefine(defs);
+            }
+        }
+    }
+
+    /// Position of each op in the block's list, indexed by slot.
+    /// Ops that are allocated but not linked in have none.
+    pub(crate) fn list_positions(&self) -> Vec<Option<usize>> {
+        let mut positions = vec![None; self.pool.slots()];
+
+        for (position, ptr) in self.ptrs().enumerate() {
+            positions[ptr.idx] = Some(position);
+        }
+
+        positions
     }
 
     pub fn len(&self) -> usize {
         self.pool.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+
     /// Traverse value definitions in each operation's operands
-    /// to create a linear sequence of operations.
+    /// to create a linear sequence of operations, going over them in order.
+    /// Each op comes after the ops in this block defining its operands,
+    /// unless they're on a cycle of ops using each other's results.
     pub fn linearize(&self) -> Vec<Ptr> {
         let mut linearized = Vec::new();
+        let mut visits = vec![Visit::New; self.pool.slots()];
 
-        for (id, op) in self.walk_ops().enumerate() {
-            for operand in &op.operands {
-                if let Some(def) = operand.def {
-                    if linearized.iter().any(|x| *x == def) {
-                        continue;
-                    }
-                    linearized.push(def);
-                }
-            }
-
-            linearized.push(id.into());
+        for ptr in self.ptrs() {
+            self.linearize_from(ptr, &mut visits, &mut linearized);
         }
 
         linearized
     }
+
+    fn linearize_from(&self, ptr: Ptr, visits: &mut [Visit], linearized: &mut Vec<Ptr>) {
+        if visits[ptr.idx] != Visit::New {
+            return;
+        }
+
+        visits[ptr.idx] = Visit::InProgress;
+
+        for operand in &self.get(ptr).operands {
+            let defined_here = operand
+                .def
+                .and_then(|def| self.pool.get(def))
+                .is_some_and(|def| def.results.contains(operand));
+
+            if defined_here {
+                let def = operand.def.expect("it was just checked");
+                self.linearize_from(def, visits, linearized);
+            }
+        }
+
+        visits[ptr.idx] = Visit::Done;
+        linearized.push(ptr);
+    }
+}
+
+/// How far `Block::linearize` got with an op. An op it runs into again
+/// while still going over its operands is on a cycle, and isn't revisited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    InProgress,
+    Done,
+}
+
+impl Default for Block {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LinkedList<Operation> for Block {
@@ -299,25 +827,353 @@ impl LinkedList<Operation> for Block {
     }
 }
 
-impl Display for Block {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, ".bb{}:", self.id)?;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    fn result_of(block: &Block, name: &str) -> Value {
+        block
+            .iter()
+            .find(|op| op.name == name)
+            .expect("op should be in the block")
+            .get_result()
+    }
+
+    #[test]
+    fn push_tracks_uses() {
+        let block = parse(
+            "\
+.bb0:
+    %0 := test.const
+    %1 := test.neg %0
+    %2 := test.add %0, %1
+",
+        )
+        .unwrap();
+
+        let c = result_of(&block, "test.const");
+        let neg = result_of(&block, "test.neg");
+        let add = result_of(&block, "test.add");
+
+        assert_eq!(block.uses(c).len(), 2);
+        assert_eq!(
+            block.users(c).collect::<Vec<_>>(),
+            vec![neg.def.unwrap(), add.def.unwrap()]
+        );
+
+        assert!(block.has_one_use(neg));
+        assert_eq!(
+            block.uses(neg),
+            &[Use {
+                op: add.def.unwrap(),
+                operand: 1
+            }]
+        );
+
+        assert!(block.uses(add).is_empty());
+    }
 
-        for op in self.iter() {
-            writeln!(f, "    {}", op)?;
+    #[test]
+    fn replace_all_uses_in_a_region() {
+        let mut block = parse(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const
+        %1 := test.const2
+        test.br .bb2
+    .bb2:
+        test.use %0
+        test.loop {
+        .bb3:
+            test.use %0
         }
+    }
+",
+        )
+        .unwrap();
+
+        let op = block.head().unwrap();
+        let region = &mut block.get_mut(op).blocks;
+        let old = region[0].iter().next().unwrap().get_result();
+        let new = region[0].iter().nth(1).unwrap().get_result();
+
+        // .bb1 doesn't see the uses in .bb2
+        assert_eq!(region[0].num_uses(old), 0);
+        assert_eq!(region[1].num_uses(old), 2);
+        assert_eq!(Block::num_uses_in(region, old), 2);
 
-        Ok(())
+        Block::replace_all_uses_in(region, old, new);
+
+        assert_eq!(Block::num_uses_in(region, old), 0);
+        assert_eq!(Block::num_uses_in(region, new), 2);
+        assert_eq!(region[1].iter().next().unwrap().operands, vec![new]);
+    }
+
+    #[test]
+    fn replace_all_uses_reaches_nested_blocks() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const
+    %1 := test.const2
+    %2 := test.neg %0
+    test.func {
+    .bb1:
+        test.ret %0
+    }
+",
+        )
+        .unwrap();
+
+        let old = result_of(&block, "test.const");
+        let new = result_of(&block, "test.const2");
+        block.replace_all_uses_with(old, new);
+
+        assert!(block.uses(old).is_empty());
+        assert_eq!(
+            block.users(new).collect::<Vec<_>>(),
+            vec![result_of(&block, "test.neg").def.unwrap()]
+        );
+
+        let neg = block.iter().find(|op| op.name == "test.neg").unwrap();
+        assert_eq!(neg.operands, vec![new]);
+
+        let func = block.iter().find(|op| op.name == "test.func").unwrap();
+        let nested = func.walk_blocks().next().unwrap();
+        assert_eq!(nested.iter().next().unwrap().operands, vec![new]);
+        assert!(nested.has_one_use(new));
+    }
+
+    #[test]
+    fn successor_args_are_uses() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const
+    %1 := test.const2
+    test.br %0, .bb1(%0, %1)
+",
+        )
+        .unwrap();
+
+        let old = result_of(&block, "test.const");
+        let new = result_of(&block, "test.const2");
+        let br = block.tail().unwrap();
+
+        assert_eq!(
+            block.uses(old),
+            &[Use { op: br, operand: 0 }, Use { op: br, operand: 1 }]
+        );
+        assert_eq!(block.uses(new), &[Use { op: br, operand: 2 }]);
+
+        block.replace_all_uses_with(old, new);
+
+        let br = block.get(br);
+        assert_eq!(br.operands, vec![new]);
+        assert_eq!(br.successors[0].args, vec![new, new]);
+        assert_eq!(block.uses(new).len(), 3);
     }
-}
 
-// this is incorect, but for now it will do
-pub fn walk_blocks<'a>(block: &'a mut Block) -> Box<dyn Iterator<Item = &'a mut Block> + 'a> {
-    let mut blocks = Vec::new();
+    #[test]
+    fn traverse_in_list_order() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.a
+    %1 := test.b %0
+    test.c %1
+",
+        )
+        .unwrap();
+
+        let c = block.tail().unwrap();
+        crate::OpBuilder::at_start(&mut block).move_op(c);
+
+        let names: Vec<_> = block.walk_ops().map(|op| op.name).collect();
+        assert_eq!(names, ["test.c", "test.a", "test.b"]);
+
+        let names: Vec<_> = block.walk_ops_mut().map(|op| op.name).collect();
+        assert_eq!(names, ["test.c", "test.a", "test.b"]);
+
+        let names: Vec<_> = block.ptrs().map(|ptr| block.get(ptr).name).collect();
+        assert_eq!(names, ["test.c", "test.a", "test.b"]);
+
+        // the defs come first, wherever their users are in the list
+        let names: Vec<_> = block
+            .linearize()
+            .into_iter()
+            .map(|ptr| block.get(ptr).name)
+            .collect();
+        assert_eq!(names, ["test.a", "test.b", "test.c"]);
+    }
+
+    #[test]
+    fn linearize_cycles() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.a
+    %1 := test.b %0
+    test.c %1
+",
+        )
+        .unwrap();
+
+        // make test.a use the result of test.b, which uses test.a's
+        let a = block.head().unwrap();
+        let b = block.get(a).ahead.unwrap();
+        let b_result = block.get(b).get_result();
+        block.get_mut(a).operands.push(b_result);
+
+        let names: Vec<_> = block
+            .linearize()
+            .into_iter()
+            .map(|ptr| block.get(ptr).name)
+            .collect();
+        assert_eq!(names, ["test.b", "test.a", "test.c"]);
+    }
 
-    for op in block.walk_ops_mut() {
-        blocks.extend(op.walk_blocks_mut());
+    #[test]
+    fn splice_moves_defs_and_uses() {
+        let mut from = parse(
+            "\
+.bb0:
+    %0 := test.a
+    %1 := test.b %0
+    test.c %1
+",
+        )
+        .unwrap();
+        let mut to = parse(".bb0:\n    test.end").unwrap();
+
+        let a = from.head().unwrap();
+        let b = from.get(a).ahead.unwrap();
+        let end = *to.head();
+        let moved = to.splice(end, &mut from, a, b);
+
+        let names: Vec<_> = to.iter().map(|op| op.name).collect();
+        assert_eq!(names, ["test.a", "test.b", "test.end"]);
+        assert_eq!(from.iter().count(), 1);
+
+        let a_result = to.get(moved[0]).get_result();
+        let b = to.get(moved[1]);
+        assert_eq!(a_result.def, Some(moved[0]));
+        assert_eq!(b.operands[0].def, Some(moved[0]));
+        assert_eq!(
+            to.uses(a_result),
+            &[Use {
+                op: moved[1],
+                operand: 0
+            }]
+        );
+        assert!(from.uses(a_result).is_empty());
+
+        // the op left behind can't point into another block
+        let c = from.head().unwrap();
+        assert_eq!(from.get(c).operands[0], b.get_result());
+        assert_eq!(from.get(c).operands[0].def, None);
     }
 
-    Box::new(blocks.into_iter())
+    #[test]
+    fn erased_ops_disappear() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const
+    %1 := test.neg %0
+    test.ret %0
+",
+        )
+        .unwrap();
+
+        let c = result_of(&block, "test.const");
+        let neg = result_of(&block, "test.neg").def.unwrap();
+
+        let erased = block.erase_op(neg);
+        assert_eq!(erased.name, "test.neg");
+
+        assert_eq!(block.len(), 2);
+        assert_eq!(block.walk_ops().count(), 2);
+        assert!(block.has_one_use(c));
+        assert!(!block.to_string().contains("test.neg"));
+
+        // the freed slot is used again, under a new generation
+        let ptr = block.push(erased);
+        assert_eq!(ptr.idx, neg.idx);
+        assert_ne!(ptr, neg);
+        assert_eq!(block.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "the slot now holds 'test.ret'")]
+    #[cfg(debug_assertions)]
+    fn stale_ptr_names_new_occupant() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const
+    %1 := test.neg %0
+",
+        )
+        .unwrap();
+
+        let neg = result_of(&block, "test.neg").def.unwrap();
+        let erased = block.erase_op(neg);
+        block.push(Operation {
+            name: "test.ret",
+            results: Vec::new(),
+            ..erased
+        });
+
+        block.get(neg);
+    }
+
+    #[test]
+    fn compact_keeps_block_consistent() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const
+    %1 := test.dead
+    %2 := test.neg %0
+    test.func {
+    .bb1:
+        test.ret %2
+    }
+",
+        )
+        .unwrap();
+
+        let dead = result_of(&block, "test.dead").def.unwrap();
+        let old_neg = result_of(&block, "test.neg").def.unwrap();
+        block.erase_op(dead);
+
+        let remap = block.compact();
+        assert_eq!(block.pool.slots(), 3);
+        assert_eq!(remap.get(dead), None);
+
+        let neg = result_of(&block, "test.neg");
+        assert_eq!(neg.def, remap.get(old_neg));
+        assert_eq!(block.get(neg.def.unwrap()).name, "test.neg");
+        assert_eq!(
+            block
+                .users(result_of(&block, "test.const"))
+                .collect::<Vec<_>>(),
+            vec![neg.def.unwrap()]
+        );
+
+        // the nested use points at the moved def too
+        let func = block.iter().find(|op| op.name == "test.func").unwrap();
+        let nested = func.walk_blocks().next().unwrap();
+        assert_eq!(nested.iter().next().unwrap().operands[0].def, neg.def);
+
+        assert_eq!(crate::verify(&block), Ok(()));
+        assert_eq!(
+            block.iter().map(|op| op.name).collect::<Vec<_>>(),
+            vec!["test.const", "test.neg", "test.func"]
+        );
+    }
 }
@@ -1,11 +1,35 @@
+pub mod analysis;
 pub mod attr;
+mod builder;
+mod context;
+pub mod dialect;
 mod ir;
-mod link;
+pub mod link;
+mod location;
+mod parse;
+pub mod pass;
+mod pattern;
 mod pool;
+mod print;
+mod rewritable;
 mod rewrite;
+pub mod symbol;
 mod transform;
+pub mod types;
+pub mod verify;
+mod walk;
 
-pub use ir::{Block, OpResult, Operation, Value, walk_blocks};
-pub use pool::{Pool, Ptr};
-pub use rewrite::{RewriteRule, RewriteRuleSet};
-pub use transform::{RewritingCtx, rewrite_ops};
+pub use builder::{InsertPoint, OpBuilder};
+pub use context::Context;
+pub use ir::{Block, Operation, Successor, Use, Value};
+pub use location::Location;
+pub use parse::{ParseError, parse};
+pub use pass::{Pass, PassError, PassManager, PassRegistry, PipelineError};
+pub use pattern::Replacement;
+pub use pool::{Pool, Ptr, Remap};
+pub use rewritable::{Rewritable, rewrite};
+pub use rewrite::{RewriteRule, RewriteRuleSet, RewriteTarget};
+pub use transform::{GreedyConfig, GreedyOutcome, RewritingCtx, rewrite_greedily, rewrite_ops};
+pub use types::Type;
+pub use verify::{Verifier, verify};
+pub use walk::{WalkOrder, WalkResult, walk_blocks, walk_blocks_mut, walk_ops, walk_ops_mut};
@@ -7,17 +7,44 @@ pub trait LinkedNode {
     fn behind_mut(&mut self) -> &mut Option<Ptr>;
 }
 
+/// The nodes of a list in order, from either end
 pub struct LinkedListIter<'a, T: LinkedNode> {
     pool: &'a Pool<T>,
-    current: Option<Ptr>,
+    front: Option<Ptr>,
+    back: Option<Ptr>,
+}
+
+impl<'a, T: LinkedNode> LinkedListIter<'a, T> {
+    /// Take the node at `ptr`, ending the iteration if it was the last one left
+    fn take(&mut self, ptr: Ptr) -> &'a T {
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        }
+
+        self.pool.deref(ptr)
+    }
 }
 
 impl<'a, T: LinkedNode> Iterator for LinkedListIter<'a, T> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> {
-        let curr_ptr = self.current?;
-        let node = self.pool.deref(curr_ptr);
-        self.current = node.ahead();
+        let curr_ptr = self.front?;
+        let node = self.take(curr_ptr);
+        if self.front.is_some() {
+            self.front = node.ahead();
+        }
+        Some(node)
+    }
+}
+
+impl<T: LinkedNode> DoubleEndedIterator for LinkedListIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let curr_ptr = self.back?;
+        let node = self.take(curr_ptr);
+        if self.back.is_some() {
+            self.back = node.behind();
+        }
         Some(node)
     }
 }
@@ -31,49 +58,124 @@ pub trait LinkedList<T: LinkedNode> {
     fn pool(&self) -> &Pool<T>;
     fn pool_mut(&mut self) -> &mut Pool<T>;
 
+    /// Insert a node in front of `root`
     fn insert_behind(&mut self, root: Ptr, inserted: T) -> Ptr {
-        let pool = self.pool_mut();
-        let inserted = pool.alloc(inserted);
+        let inserted = self.pool_mut().alloc(inserted);
+        self.link_behind(root, inserted);
+        inserted
+    }
+
+    /// Insert a node right after `root`
+    fn insert_ahead(&mut self, root: Ptr, inserted: T) -> Ptr {
+        let inserted = self.pool_mut().alloc(inserted);
+        self.link_ahead(root, inserted);
+        inserted
+    }
+
+    fn push(&mut self, node: T) -> Ptr {
+        let node = self.pool_mut().alloc(node);
+        self.link_back(node);
+        node
+    }
 
-        if let Some(behind) = *pool.deref_mut(root).behind_mut() {
-            // link up inserted node between the old behind node and the root
-            *pool.deref_mut(inserted).behind_mut() = Some(behind);
-            *pool.deref_mut(inserted).ahead_mut() = Some(root);
+    /// Link a detached node in front of `root`
+    fn link_behind(&mut self, root: Ptr, node: Ptr) {
+        let behind = self.pool().deref(root).behind();
 
-            // the old behind node now points to inserted
-            *pool.deref_mut(behind).ahead_mut() = Some(inserted);
+        *self.pool_mut().deref_mut(node).behind_mut() = behind;
+        *self.pool_mut().deref_mut(node).ahead_mut() = Some(root);
+        *self.pool_mut().deref_mut(root).behind_mut() = Some(node);
 
-            // point the root's behind ptr to the inserted node
-            *pool.deref_mut(root).behind_mut() = Some(inserted);
+        match behind {
+            Some(behind) => *self.pool_mut().deref_mut(behind).ahead_mut() = Some(node),
+            None => *self.head_mut() = Some(node),
         }
+    }
 
-        inserted
+    /// Link a detached node right after `root`
+    fn link_ahead(&mut self, root: Ptr, node: Ptr) {
+        let ahead = self.pool().deref(root).ahead();
+
+        *self.pool_mut().deref_mut(node).ahead_mut() = ahead;
+        *self.pool_mut().deref_mut(node).behind_mut() = Some(root);
+        *self.pool_mut().deref_mut(root).ahead_mut() = Some(node);
+
+        match ahead {
+            Some(ahead) => *self.pool_mut().deref_mut(ahead).behind_mut() = Some(node),
+            None => *self.tail_mut() = Some(node),
+        }
     }
 
-    fn push(&mut self, node: T) -> Ptr {
-        let node = self.pool_mut().alloc(node);
+    /// Link a detached node at the end of the list
+    fn link_back(&mut self, node: Ptr) {
+        match *self.tail() {
+            Some(tail) => self.link_ahead(tail, node),
+            None => {
+                *self.head_mut() = Some(node);
+                *self.tail_mut() = Some(node);
+            }
+        }
+    }
 
-        if let Some(tail_ptr) = *self.tail_mut() {
-            let tail = self.pool_mut().deref_mut(tail_ptr);
-            *tail.ahead_mut() = Some(node);
+    /// Detach a node from the list, leaving it in the pool
+    fn unlink(&mut self, node: Ptr) {
+        let (behind, ahead) = {
+            let node = self.pool().deref(node);
+            (node.behind(), node.ahead())
+        };
+
+        if let Some(behind) = behind {
+            *self.pool_mut().deref_mut(behind).ahead_mut() = ahead;
+        } else if *self.head() == Some(node) {
+            *self.head_mut() = ahead;
+        }
 
-            let node = self.pool_mut().deref_mut(node);
-            *node.behind_mut() = Some(tail_ptr)
+        if let Some(ahead) = ahead {
+            *self.pool_mut().deref_mut(ahead).behind_mut() = behind;
+        } else if *self.tail() == Some(node) {
+            *self.tail_mut() = behind;
         }
 
-        *self.tail_mut() = Some(node);
+        let node = self.pool_mut().deref_mut(node);
+        *node.behind_mut() = None;
+        *node.ahead_mut() = None;
+    }
 
-        if self.head().is_none() {
-            *self.head_mut() = Some(node);
+    /// Move the nodes from `first` through `last` out of `other`, linking them
+    /// in front of `before`, or at the end without it.
+    ///
+    /// They take new slots in this list's pool, which are returned in order.
+    fn splice(&mut self, before: Option<Ptr>, other: &mut Self, first: Ptr, last: Ptr) -> Vec<Ptr>
+    where
+        Self: Sized,
+    {
+        let mut moved = Vec::new();
+        let mut next = Some(first);
+
+        while let Some(ptr) = next {
+            next = (ptr != last)
+                .then(|| other.pool().deref(ptr).ahead())
+                .flatten();
+
+            other.unlink(ptr);
+            let node = self.pool_mut().alloc(other.pool_mut().free(ptr));
+
+            match before {
+                Some(before) => self.link_behind(before, node),
+                None => self.link_back(node),
+            }
+
+            moved.push(node);
         }
 
-        node
+        moved
     }
 
-    fn iter(&self) -> LinkedListIter<T> {
+    fn iter(&self) -> LinkedListIter<'_, T> {
         LinkedListIter {
             pool: self.pool(),
-            current: *self.head(),
+            front: *self.head(),
+            back: *self.tail(),
         }
     }
 }
@@ -81,18 +183,14 @@ pub trait LinkedList<T: LinkedNode> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{Block, Operation, Value, attr::AttributeMap};
+    use crate::{Block, Operation, Value};
     use proptest::prelude::*;
 
     fn dummy(src: Value, dst: Value) -> Operation {
         Operation {
-            name: "test.dummy",
             operands: vec![src],
-            blocks: Vec::new(),
-            result: Some(dst),
-            attributes: AttributeMap::new(),
-            behind: None,
-            ahead: None,
+            results: vec![dst],
+            ..Operation::new("test.dummy")
         }
     }
 
@@ -147,6 +245,78 @@ mod test {
         assert_eq!(bl.pool().deref(ptr3).behind(), Some(ptr1));
     }
 
+    #[test]
+    fn insert_at_the_ends() {
+        let mut bl = Block::new();
+        let ptr1 = bl.push(dummy(val(), val()));
+
+        let head = bl.insert_behind(ptr1, dummy(val(), val()));
+        assert_eq!(*bl.head(), Some(head));
+        assert_eq!(bl.pool().deref(head).ahead(), Some(ptr1));
+        assert_eq!(bl.pool().deref(ptr1).behind(), Some(head));
+
+        let tail = bl.insert_ahead(ptr1, dummy(val(), val()));
+        assert_eq!(*bl.tail(), Some(tail));
+        assert_eq!(bl.pool().deref(tail).behind(), Some(ptr1));
+        assert_eq!(bl.pool().deref(ptr1).ahead(), Some(tail));
+        assert_eq!(bl.iter().count(), 3);
+    }
+
+    #[test]
+    fn iterate_from_both_ends() {
+        let mut bl = Block::new();
+        let vals: Vec<_> = (0..4).map(|_| val()).collect();
+        for &v in &vals {
+            bl.push(dummy(val(), v));
+        }
+
+        let tags =
+            |ops: Vec<&Operation>| -> Vec<Value> { ops.iter().map(|op| op.results[0]).collect() };
+
+        let backward = tags(bl.iter().rev().collect());
+        assert_eq!(backward, vals.iter().rev().copied().collect::<Vec<_>>());
+
+        // the ends meet in the middle without visiting anything twice
+        let mut iter = bl.iter();
+        let ends = [
+            iter.next(),
+            iter.next_back(),
+            iter.next(),
+            iter.next_back(),
+            iter.next(),
+        ];
+        let ends: Vec<_> = ends.iter().map(|op| op.map(|op| op.results[0])).collect();
+        assert_eq!(
+            ends,
+            [
+                Some(vals[0]),
+                Some(vals[3]),
+                Some(vals[1]),
+                Some(vals[2]),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn splice_between_lists() {
+        let mut from = Block::new();
+        let vals: Vec<_> = (0..4).map(|_| val()).collect();
+        let ptrs: Vec<_> = vals.iter().map(|&v| from.push(dummy(val(), v))).collect();
+
+        let mut to = Block::new();
+        let end = to.push(dummy(val(), val()));
+
+        let moved = LinkedList::splice(&mut to, Some(end), &mut from, ptrs[1], ptrs[2]);
+        assert_eq!(moved.len(), 2);
+        assert_eq!(*to.head(), Some(moved[0]));
+        assert_eq!(to.pool().deref(moved[1]).ahead(), Some(end));
+
+        let left: Vec<_> = from.iter().map(|op| op.results[0]).collect();
+        assert_eq!(left, [vals[0], vals[3]]);
+        assert_eq!(from.pool().len(), 2);
+    }
+
     #[test]
     fn empty_and_single_element_list() {
         let mut bl = Block::new();
@@ -174,7 +344,130 @@ mod test {
         assert_eq!(bl.pool().deref(ptrs[0]).behind(), None);
     }
 
+    #[test]
+    fn unlink_head_middle_and_tail() {
+        let mut bl = Block::new();
+        let ptrs: Vec<_> = (0..4).map(|_| bl.push(dummy(val(), val()))).collect();
+
+        bl.unlink(ptrs[1]);
+        assert_eq!(bl.pool().deref(ptrs[0]).ahead(), Some(ptrs[2]));
+        assert_eq!(bl.pool().deref(ptrs[2]).behind(), Some(ptrs[0]));
+        assert_eq!(bl.pool().deref(ptrs[1]).ahead(), None);
+        assert_eq!(bl.pool().deref(ptrs[1]).behind(), None);
+
+        bl.unlink(ptrs[0]);
+        assert_eq!(*bl.head(), Some(ptrs[2]));
+        assert_eq!(bl.pool().deref(ptrs[2]).behind(), None);
+
+        bl.unlink(ptrs[3]);
+        assert_eq!(*bl.tail(), Some(ptrs[2]));
+        assert_eq!(bl.pool().deref(ptrs[2]).ahead(), None);
+
+        bl.unlink(ptrs[2]);
+        assert!(bl.head().is_none());
+        assert!(bl.tail().is_none());
+        assert_eq!(bl.iter().count(), 0);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Edit {
+        Push,
+        InsertBehind(usize),
+        InsertAhead(usize),
+        Unlink(usize),
+    }
+
+    fn edit() -> impl Strategy<Value = Edit> {
+        prop_oneof![
+            Just(Edit::Push),
+            any::<usize>().prop_map(Edit::InsertBehind),
+            any::<usize>().prop_map(Edit::InsertAhead),
+            any::<usize>().prop_map(Edit::Unlink),
+        ]
+    }
+
+    /// Check the list holds `model`, in order from both ends and with every link agreeing
+    fn check_list(bl: &Block, model: &[Ptr]) -> Result<(), TestCaseError> {
+        let forward: Vec<_> =
+            std::iter::successors(*bl.head(), |&p| bl.pool().deref(p).ahead()).collect();
+        let backward: Vec<_> =
+            std::iter::successors(*bl.tail(), |&p| bl.pool().deref(p).behind()).collect();
+
+        prop_assert_eq!(&forward, model);
+        prop_assert_eq!(backward.into_iter().rev().collect::<Vec<_>>(), model);
+        prop_assert_eq!(bl.iter().count(), model.len());
+        prop_assert_eq!(bl.iter().rev().count(), model.len());
+        Ok(())
+    }
+
     proptest! {
+        #[test]
+        fn edits_keep_the_list_consistent(edits in prop::collection::vec(edit(), 0..64)) {
+            let mut bl = Block::new();
+            let mut model: Vec<Ptr> = Vec::new();
+
+            for edit in edits {
+                match edit {
+                    Edit::Push => model.push(bl.push(dummy(val(), val()))),
+                    Edit::InsertBehind(i) if !model.is_empty() => {
+                        let i = i % model.len();
+                        let ptr = bl.insert_behind(model[i], dummy(val(), val()));
+                        model.insert(i, ptr);
+                    }
+                    Edit::InsertAhead(i) if !model.is_empty() => {
+                        let i = i % model.len();
+                        let ptr = bl.insert_ahead(model[i], dummy(val(), val()));
+                        model.insert(i + 1, ptr);
+                    }
+                    Edit::Unlink(i) if !model.is_empty() => {
+                        let ptr = model.remove(i % model.len());
+                        bl.unlink(ptr);
+                    }
+                    _ => (),
+                }
+
+                check_list(&bl, &model)?;
+            }
+        }
+
+        #[test]
+        fn splice_moves_ranges(
+            len in 1usize..16,
+            range in (any::<usize>(), any::<usize>()),
+            to_len in 0usize..4,
+            at in any::<usize>(),
+        ) {
+            let mut from = Block::new();
+            let tags: Vec<_> = (0..len).map(|_| val()).collect();
+            let ptrs: Vec<_> = tags.iter().map(|&tag| from.push(dummy(val(), tag))).collect();
+            let (first, last) = {
+                let (a, b) = (range.0 % len, range.1 % len);
+                (a.min(b), a.max(b))
+            };
+
+            let mut to = Block::new();
+            let mut to_model: Vec<_> = (0..to_len).map(|_| to.push(dummy(val(), val()))).collect();
+            let before = (to_len > 0).then(|| at % (to_len + 1)).filter(|&i| i < to_len);
+
+            let moved = LinkedList::splice(
+                &mut to,
+                before.map(|i| to_model[i]),
+                &mut from,
+                ptrs[first],
+                ptrs[last],
+            );
+
+            let at = before.unwrap_or(to_len);
+            to_model.splice(at..at, moved.iter().copied());
+            check_list(&to, &to_model)?;
+
+            let left: Vec<_> = ptrs[..first].iter().chain(&ptrs[last + 1..]).copied().collect();
+            check_list(&from, &left)?;
+
+            let moved_tags: Vec<_> = moved.iter().map(|&p| to.pool().deref(p).results[0]).collect();
+            prop_assert_eq!(moved_tags, &tags[first..=last]);
+        }
+
         #[test]
         fn push_many(count in 0usize..10000) {
             let mut bl = Block::new();
@@ -0,0 +1,143 @@
+// Where in the source an op came from
+
+use std::fmt::Display;
+
+/// The source an op was made from, printed after it as `loc(...)`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Location {
+    #[default]
+    Unknown,
+    /// A range in a file, with lines and columns counted from 1 and the end exclusive
+    Span {
+        file: &'static str,
+        line: usize,
+        col: usize,
+        end_line: usize,
+        end_col: usize,
+    },
+    /// An op made from several others, e.g. by folding them together
+    Fused(Vec<Location>),
+    /// Code from `callee` that was inlined at `caller`
+    CallSite {
+        callee: Box<Location>,
+        caller: Box<Location>,
+    },
+}
+
+impl Location {
+    /// The span of `len` characters at `line` and `col`, on a single line
+    pub fn span(file: &str, line: usize, col: usize, len: usize) -> Self {
+        Location::Span {
+            file: crate::parse::intern(file),
+            line,
+            col,
+            end_line: line,
+            end_col: col + len,
+        }
+    }
+
+    /// All of `locations`, leaving out unknown ones and flattening nested fusions
+    pub fn fused(locations: impl IntoIterator<Item = Location>) -> Self {
+        let mut fused = Vec::new();
+
+        for location in locations {
+            match location {
+                Location::Unknown => (),
+                Location::Fused(inner) => fused.extend(inner),
+                location if fused.contains(&location) => (),
+                location => fused.push(location),
+            }
+        }
+
+        match fused.len() {
+            0 => Location::Unknown,
+            1 => fused.pop().expect("there's one location"),
+            _ => Location::Fused(fused),
+        }
+    }
+
+    pub fn call_site(callee: Location, caller: Location) -> Self {
+        Location::CallSite {
+            callee: Box::new(callee),
+            caller: Box::new(caller),
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, Location::Unknown)
+    }
+}
+
+/// The inside of `loc(...)`
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Unknown => write!(f, "unknown"),
+            Location::Span {
+                file,
+                line,
+                col,
+                end_line,
+                end_col,
+            } => {
+                // escaped the way the parser reads strings back
+                crate::attr::fmt_str(file, f)?;
+                write!(f, ":{}:{}", line, col)?;
+
+                if (line, col) != (end_line, end_col) {
+                    write!(f, " to {}:{}", end_line, end_col)?;
+                }
+
+                Ok(())
+            }
+            Location::Fused(locations) => {
+                write!(f, "fused[")?;
+                for (i, location) in locations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", location)?;
+                }
+                write!(f, "]")
+            }
+            Location::CallSite { callee, caller } => {
+                write!(f, "callsite({} at {})", callee, caller)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn print_locations() {
+        let main = Location::span("main.c", 3, 5, 4);
+        let point = Location::span("lib.c", 1, 1, 0);
+
+        assert_eq!(main.to_string(), "\"main.c\":3:5 to 3:9");
+        assert_eq!(point.to_string(), "\"lib.c\":1:1");
+        assert_eq!(
+            Location::fused([main.clone(), Location::Unknown, point.clone()]).to_string(),
+            "fused[\"main.c\":3:5 to 3:9, \"lib.c\":1:1]"
+        );
+        assert_eq!(
+            Location::call_site(point, main).to_string(),
+            "callsite(\"lib.c\":1:1 at \"main.c\":3:5 to 3:9)"
+        );
+    }
+
+    #[test]
+    fn fuse_locations() {
+        let a = Location::span("a.c", 1, 1, 1);
+        let b = Location::span("b.c", 2, 2, 2);
+
+        assert_eq!(Location::fused([]), Location::Unknown);
+        assert_eq!(Location::fused([a.clone(), a.clone()]), a);
+        assert_eq!(
+            Location::fused([Location::fused([a.clone(), b.clone()]), a.clone()]),
+            Location::Fused(vec![a, b])
+        );
+    }
+}
@@ -0,0 +1,1085 @@
+// Parse the textual form of the IR, as produced by `Display for Block`
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+use std::sync::Mutex;
+
+use crate::attr::{Attribute, AttributeMap};
+use crate::types::{self, Type};
+use crate::{Block, Location, Operation, Ptr, Successor, Value};
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind<'src> {
+    Value(&'src str),
+    Label(&'src str),
+    Ident(&'src str),
+    Int(&'src str),
+    Float(&'src str),
+    /// The contents of a string, still escaped
+    Str(&'src str),
+    Symbol(&'src str),
+    /// A type, and the text it was read from, which could also be a name
+    Type(Type, &'src str),
+    Define,
+    Punct(char),
+    Newline,
+    Eof,
+}
+
+impl Display for TokenKind<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Value(name) => write!(f, "'%{}'", name),
+            TokenKind::Label(name) => write!(f, "'.{}'", name),
+            TokenKind::Ident(name) | TokenKind::Int(name) | TokenKind::Float(name) => {
+                write!(f, "'{}'", name)
+            }
+            TokenKind::Str(s) => write!(f, "'\"{}\"'", s),
+            TokenKind::Symbol(name) => write!(f, "'@{}'", name),
+            TokenKind::Type(_, text) => write!(f, "'{}'", text),
+            TokenKind::Define => write!(f, "':='"),
+            TokenKind::Punct(c) => write!(f, "'{}'", c),
+            TokenKind::Newline => write!(f, "end of line"),
+            TokenKind::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'src> {
+    kind: TokenKind<'src>,
+    line: usize,
+}
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(src: &str) -> ParseResult<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    let mut line = 1;
+
+    // consume characters matching `pred`, returning the end offset
+    let eat_while = |chars: &mut std::iter::Peekable<std::str::CharIndices>,
+                     pred: fn(char) -> bool| {
+        while chars.next_if(|&(_, c)| pred(c)).is_some() {}
+        chars.peek().map_or(src.len(), |&(i, _)| i)
+    };
+
+    while let Some((start, c)) = chars.next() {
+        // a word that reads as a type, like `i32` or `ptr<u8>`, is one
+        if (is_word(c) || c == '!')
+            && let Some((ty, rest)) = types::parse_prefix(&src[start..])
+            && !rest.starts_with(|c| is_word(c) || c == '.')
+        {
+            let end = src.len() - rest.len();
+            while chars.next_if(|&(i, _)| i < end).is_some() {}
+
+            tokens.push(Token {
+                kind: TokenKind::Type(ty, &src[start..end]),
+                line,
+            });
+            continue;
+        }
+
+        let kind = match c {
+            '\n' => TokenKind::Newline,
+            c if c.is_whitespace() => continue,
+
+            '/' if chars.next_if(|&(_, c)| c == '/').is_some() => {
+                eat_while(&mut chars, |c| c != '\n');
+                continue;
+            }
+
+            '%' => TokenKind::Value(&src[start + 1..eat_while(&mut chars, is_word)]),
+            '.' => TokenKind::Label(&src[start + 1..eat_while(&mut chars, is_word)]),
+            '@' => TokenKind::Symbol(&src[start + 1..eat_while(&mut chars, is_word)]),
+
+            // negative infinity, the one float that's not a number nor a word
+            '-' if src[start + 1..].starts_with("inf")
+                && !src[start + 4..].starts_with(|c| is_word(c) || c == '.') =>
+            {
+                chars.nth(2);
+                TokenKind::Float(&src[start..start + 4])
+            }
+
+            c if c.is_ascii_digit()
+                || (c == '-' && chars.peek().is_some_and(|&(_, c)| c.is_ascii_digit())) =>
+            {
+                let mut end = eat_while(&mut chars, |c| c.is_ascii_digit());
+                let mut float = false;
+
+                // a point followed by more digits makes it a float
+                if src[end..].starts_with('.')
+                    && src[end + 1..].starts_with(|c: char| c.is_ascii_digit())
+                {
+                    chars.next();
+                    end = eat_while(&mut chars, |c| c.is_ascii_digit());
+                    float = true;
+                }
+
+                // and so does an exponent, like in `1e100` or `2.5e-7`
+                if let Some(exp) = src[end..].strip_prefix(['e', 'E'])
+                    && exp
+                        .strip_prefix(['+', '-'])
+                        .unwrap_or(exp)
+                        .starts_with(|c: char| c.is_ascii_digit())
+                {
+                    chars.next();
+                    chars.next_if(|&(_, c)| c == '+' || c == '-');
+                    end = eat_while(&mut chars, |c| c.is_ascii_digit());
+                    float = true;
+                }
+
+                if float {
+                    TokenKind::Float(&src[start..end])
+                } else {
+                    TokenKind::Int(&src[start..end])
+                }
+            }
+            c if is_word(c) => {
+                TokenKind::Ident(&src[start..eat_while(&mut chars, |c| is_word(c) || c == '.')])
+            }
+
+            '"' => {
+                let mut escaped = false;
+                let end = loop {
+                    match chars.next() {
+                        Some((_, '\\')) if !escaped => escaped = true,
+                        Some((end, '"')) if !escaped => break end,
+                        Some((_, '\n')) | None => {
+                            return Err(ParseError {
+                                line,
+                                msg: "unterminated string".to_owned(),
+                            });
+                        }
+                        Some(_) => escaped = false,
+                    }
+                };
+
+                TokenKind::Str(&src[start + 1..end])
+            }
+
+            ':' if chars.next_if(|&(_, c)| c == '=').is_some() => TokenKind::Define,
+            '{' | '}' | '[' | ']' | '(' | ')' | ',' | '=' | ':' => TokenKind::Punct(c),
+
+            c => {
+                return Err(ParseError {
+                    line,
+                    msg: format!("unexpected character '{}'", c),
+                });
+            }
+        };
+
+        tokens.push(Token { kind, line });
+
+        if kind == TokenKind::Newline {
+            line += 1;
+        }
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        line,
+    });
+
+    Ok(tokens)
+}
+
+/// Op names are `&'static str`, so names read from text are leaked once and reused.
+pub(crate) fn intern(name: &str) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+    let mut names = NAMES.lock().expect("op name interner was poisoned");
+
+    if let Some(name) = names.get(name) {
+        return name;
+    }
+
+    let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+    names.insert(name);
+    name
+}
+
+/// Undo the escapes `Display for Attribute` puts in strings
+fn unescape(escaped: &str) -> String {
+    let mut s = String::new();
+    let mut chars = escaped.chars();
+
+    while let Some(c) = chars.next() {
+        s.push(match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c) => c,
+                None => break,
+            },
+            c => c,
+        });
+    }
+
+    s
+}
+
+/// The names defined in a region. Printing renumbers the blocks nested in
+/// each op, so names are only unique within a region and the ones enclosing it.
+#[derive(Default)]
+struct Scope<'src> {
+    values: HashMap<&'src str, Value>,
+    // ids are handed out on first sight, so branches can name blocks further down
+    blocks: HashMap<&'src str, usize>,
+}
+
+struct Parser<'src> {
+    tokens: Vec<Token<'src>>,
+    pos: usize,
+
+    scopes: Vec<Scope<'src>>,
+}
+
+impl<'src> Parser<'src> {
+    fn peek(&self) -> TokenKind<'src> {
+        self.tokens[self.pos].kind
+    }
+
+    fn take(&mut self) -> TokenKind<'src> {
+        let kind = self.peek();
+
+        if kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+
+        kind
+    }
+
+    fn error<T>(&self, msg: String) -> ParseResult<T> {
+        Err(ParseError {
+            line: self.tokens[self.pos].line,
+            msg,
+        })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> ParseResult<T> {
+        self.error(format!("expected {}, but got {}", expected, self.peek()))
+    }
+
+    fn eat(&mut self, kind: TokenKind<'src>) -> bool {
+        if self.peek() == kind {
+            self.take();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind<'src>) -> ParseResult<()> {
+        if self.eat(kind) {
+            Ok(())
+        } else {
+            self.unexpected(&kind.to_string())
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.eat(TokenKind::Newline) {}
+    }
+
+    fn scope(&mut self) -> &mut Scope<'src> {
+        self.scopes.last_mut().expect("there's always a scope")
+    }
+
+    /// A value in the current region, named `name` if that's not just a number
+    fn new_value(&mut self, name: &'src str) -> Value {
+        let mut val = Value::new(None);
+
+        if !name.bytes().all(|b| b.is_ascii_digit()) {
+            val = val.with_name(name);
+        }
+
+        self.scope().values.insert(name, val);
+        val
+    }
+
+    /// Values are created the first time their name is seen, wherever that is.
+    fn value(&mut self, name: &'src str) -> Value {
+        match self.scopes.iter().rev().find_map(|s| s.values.get(name)) {
+            Some(&val) => val,
+            None => self.new_value(name),
+        }
+    }
+
+    /// A value defined by an op or block, typed if the text gives it a type.
+    /// It shadows any value of the same name from enclosing regions.
+    fn result(&mut self, name: &'src str, ty: Option<Type>) -> Value {
+        let mut val = match self.scope().values.get(name) {
+            Some(&val) => val,
+            None => self.new_value(name),
+        };
+
+        if ty.is_some() {
+            val.ty = ty;
+            self.scope().values.insert(name, val);
+        }
+
+        val
+    }
+
+    /// Blocks can only be named from their own region
+    fn block_id(&mut self, name: &'src str) -> usize {
+        *self
+            .scope()
+            .blocks
+            .entry(name)
+            .or_insert_with(Block::unique_id)
+    }
+
+    /// A value with an optional type, like `%0: i32`
+    fn parse_typed_value(&mut self) -> ParseResult<(&'src str, Option<Type>)> {
+        let TokenKind::Value(name) = self.peek() else {
+            return self.unexpected("a value");
+        };
+        self.take();
+
+        let ty = if self.eat(TokenKind::Punct(':')) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        Ok((name, ty))
+    }
+
+    /// A parenthesized list of values, like `(%0, %1)`, or nothing if there's no `(`
+    fn parse_value_list(&mut self) -> ParseResult<Vec<Value>> {
+        let mut values = Vec::new();
+
+        if !self.eat(TokenKind::Punct('(')) {
+            return Ok(values);
+        }
+
+        while !self.eat(TokenKind::Punct(')')) {
+            let TokenKind::Value(name) = self.peek() else {
+                return self.unexpected("a value");
+            };
+            self.take();
+            values.push(self.value(name));
+
+            if !self.eat(TokenKind::Punct(',')) {
+                self.expect(TokenKind::Punct(')'))?;
+                break;
+            }
+        }
+
+        Ok(values)
+    }
+
+    fn parse_type(&mut self) -> ParseResult<Type> {
+        match self.peek() {
+            TokenKind::Type(ty, _) => {
+                self.take();
+                Ok(ty)
+            }
+            _ => self.unexpected("a type"),
+        }
+    }
+
+    /// An integer, with its type if one follows it
+    fn parse_int(&mut self, digits: &str) -> ParseResult<Attribute> {
+        self.take();
+
+        let ty = if self.eat(TokenKind::Punct(':')) {
+            self.parse_type()?
+        } else {
+            Type::int(64, true)
+        };
+
+        match digits.parse() {
+            Ok(value) if Attribute::int_fits(value, ty) => Ok(Attribute::Int(value, ty)),
+            _ => self.error(format!("'{}' isn't a valid {}", digits, ty)),
+        }
+    }
+
+    fn parse_number(&mut self) -> ParseResult<usize> {
+        match self.peek() {
+            TokenKind::Int(digits) => match digits.parse() {
+                Ok(n) => {
+                    self.take();
+                    Ok(n)
+                }
+                Err(_) => self.error(format!("'{}' isn't a line or column", digits)),
+            },
+            _ => self.unexpected("a line or column"),
+        }
+    }
+
+    /// A line and column, like `3:5`
+    fn parse_line_col(&mut self) -> ParseResult<(usize, usize)> {
+        let line = self.parse_number()?;
+        self.expect(TokenKind::Punct(':'))?;
+        let col = self.parse_number()?;
+
+        Ok((line, col))
+    }
+
+    /// The inside of `loc(...)`
+    fn parse_location(&mut self) -> ParseResult<Location> {
+        match self.peek() {
+            TokenKind::Ident("unknown") => {
+                self.take();
+                Ok(Location::Unknown)
+            }
+            TokenKind::Ident("fused") => {
+                self.take();
+                self.expect(TokenKind::Punct('['))?;
+
+                let mut locations = Vec::new();
+                while !self.eat(TokenKind::Punct(']')) {
+                    locations.push(self.parse_location()?);
+
+                    if !self.eat(TokenKind::Punct(',')) {
+                        self.expect(TokenKind::Punct(']'))?;
+                        break;
+                    }
+                }
+
+                Ok(Location::Fused(locations))
+            }
+            TokenKind::Ident("callsite") => {
+                self.take();
+                self.expect(TokenKind::Punct('('))?;
+                let callee = self.parse_location()?;
+                self.expect(TokenKind::Ident("at"))?;
+                let caller = self.parse_location()?;
+                self.expect(TokenKind::Punct(')'))?;
+
+                Ok(Location::call_site(callee, caller))
+            }
+            TokenKind::Str(file) => {
+                self.take();
+                self.expect(TokenKind::Punct(':'))?;
+                let (line, col) = self.parse_line_col()?;
+
+                let (end_line, end_col) = if self.eat(TokenKind::Ident("to")) {
+                    self.parse_line_col()?
+                } else {
+                    (line, col)
+                };
+
+                Ok(Location::Span {
+                    file: intern(&unescape(file)),
+                    line,
+                    col,
+                    end_line,
+                    end_col,
+                })
+            }
+            _ => self.unexpected("a location"),
+        }
+    }
+
+    fn parse_attr(&mut self) -> ParseResult<Attribute> {
+        let attr = match self.peek() {
+            TokenKind::Int(digits) => return self.parse_int(digits),
+            TokenKind::Float(digits) => match digits.parse() {
+                Ok(value) => Attribute::Float(value),
+                Err(_) => return self.error(format!("'{}' isn't a valid float", digits)),
+            },
+            TokenKind::Ident("inf") => Attribute::Float(f64::INFINITY),
+            TokenKind::Ident("nan") => Attribute::Float(f64::NAN),
+            TokenKind::Str(escaped) => Attribute::Str(unescape(escaped)),
+            TokenKind::Ident("true") => Attribute::Bool(true),
+            TokenKind::Ident("false") => Attribute::Bool(false),
+            TokenKind::Symbol(name) => Attribute::Symbol(name.to_owned()),
+            TokenKind::Type(ty, _) => Attribute::Type(ty),
+
+            TokenKind::Punct('[') => {
+                self.take();
+                let mut items = Vec::new();
+
+                while !self.eat(TokenKind::Punct(']')) {
+                    items.push(self.parse_attr()?);
+
+                    if !self.eat(TokenKind::Punct(',')) {
+                        self.expect(TokenKind::Punct(']'))?;
+                        break;
+                    }
+                }
+
+                return Ok(Attribute::Array(items));
+            }
+            TokenKind::Punct('{') => {
+                self.take();
+                return Ok(Attribute::Dict(self.parse_attr_dict()?));
+            }
+
+            _ => return self.unexpected("an attribute"),
+        };
+
+        self.take();
+        Ok(attr)
+    }
+
+    /// The entries of a dict, after its `{`
+    fn parse_attr_dict(&mut self) -> ParseResult<AttributeMap> {
+        let mut attributes = AttributeMap::new();
+
+        while !self.eat(TokenKind::Punct('}')) {
+            // a name like `u8` reads as a type, but is a name here
+            let key = match self.peek() {
+                TokenKind::Ident(key) => key,
+                TokenKind::Type(_, key) if key.chars().all(is_word) => key,
+                _ => return self.unexpected("an attribute name"),
+            };
+            self.take();
+
+            self.expect(TokenKind::Punct('='))?;
+            attributes.insert(key.to_owned(), self.parse_attr()?);
+
+            if !self.eat(TokenKind::Punct(',')) {
+                self.expect(TokenKind::Punct('}'))?;
+                break;
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    fn parse_region(&mut self) -> ParseResult<Vec<Block>> {
+        let mut blocks = Vec::new();
+        self.scopes.push(Scope::default());
+
+        while let TokenKind::Label(_) = self.peek() {
+            blocks.push(self.parse_block()?);
+        }
+
+        patch_defs(&mut blocks);
+        self.scopes.pop();
+        self.expect(TokenKind::Punct('}'))?;
+
+        Ok(blocks)
+    }
+
+    fn parse_op(&mut self, block: &mut Block) -> ParseResult<()> {
+        let mut results = Vec::new();
+
+        if let TokenKind::Value(_) = self.peek() {
+            results.push(self.parse_typed_value()?);
+
+            while self.eat(TokenKind::Punct(',')) {
+                results.push(self.parse_typed_value()?);
+            }
+
+            self.expect(TokenKind::Define)?;
+        }
+
+        let TokenKind::Ident(name) = self.peek() else {
+            return self.unexpected("an operation name");
+        };
+        self.take();
+
+        let mut op = Operation {
+            name: intern(name),
+            operands: Vec::new(),
+            successors: Vec::new(),
+            blocks: Vec::new(),
+            results: results
+                .iter()
+                .map(|&(name, ty)| self.result(name, ty))
+                .collect(),
+
+            attributes: AttributeMap::new(),
+
+            location: Location::Unknown,
+
+            behind: None,
+            ahead: None,
+        };
+
+        // operands, then the successors with the values passed to them
+        if let TokenKind::Value(_) | TokenKind::Label(_) = self.peek() {
+            loop {
+                match self.peek() {
+                    TokenKind::Value(name) if op.successors.is_empty() => {
+                        self.take();
+                        op.operands.push(self.value(name));
+                    }
+                    TokenKind::Label(name) => {
+                        self.take();
+                        let block = self.block_id(name);
+                        let args = self.parse_value_list()?;
+                        op.successors.push(Successor { block, args });
+                    }
+                    _ if op.successors.is_empty() => return self.unexpected("an operand"),
+                    _ => return self.unexpected("a successor"),
+                }
+
+                if !self.eat(TokenKind::Punct(',')) {
+                    break;
+                }
+            }
+        }
+
+        // `{` opens an attribute dict, unless the line ends there and a region follows
+        while self.eat(TokenKind::Punct('{')) {
+            if self.eat(TokenKind::Newline) {
+                self.skip_newlines();
+                op.blocks = self.parse_region()?;
+                break;
+            }
+
+            op.attributes = self.parse_attr_dict()?;
+        }
+
+        if self.eat(TokenKind::Ident("loc")) {
+            self.expect(TokenKind::Punct('('))?;
+            op.location = self.parse_location()?;
+            self.expect(TokenKind::Punct(')'))?;
+        }
+
+        if !self.eat(TokenKind::Eof) {
+            self.expect(TokenKind::Newline)?;
+        }
+        self.skip_newlines();
+
+        let ptr = block.push(op);
+
+        // pushing fills in the defs of values this is the first op to produce
+        for (&(name, _), &val) in results.iter().zip(&block.get(ptr).results) {
+            self.scope().values.insert(name, val);
+        }
+
+        Ok(())
+    }
+
+    fn parse_block(&mut self) -> ParseResult<Block> {
+        let TokenKind::Label(name) = self.peek() else {
+            return self.unexpected("a block label");
+        };
+        self.take();
+
+        let mut block = Block::with_id(self.block_id(name));
+
+        if self.eat(TokenKind::Punct('(')) {
+            while !self.eat(TokenKind::Punct(')')) {
+                let (name, ty) = self.parse_typed_value()?;
+                let arg = self.result(name, ty);
+                block.args.push(arg);
+
+                if !self.eat(TokenKind::Punct(',')) {
+                    self.expect(TokenKind::Punct(')'))?;
+                    break;
+                }
+            }
+        }
+
+        self.expect(TokenKind::Punct(':'))?;
+        self.skip_newlines();
+
+        while let TokenKind::Value(_) | TokenKind::Ident(_) = self.peek() {
+            self.parse_op(&mut block)?;
+        }
+
+        Ok(block)
+    }
+}
+
+/// Point operands at the defs of the values they name, once the region is
+/// parsed. A block can use values of a block dominating it that's printed
+/// further down, whose defs weren't known yet when the use was parsed.
+fn patch_defs(region: &mut [Block]) {
+    let defs: HashMap<Value, Option<Ptr>> = region
+        .iter()
+        .flat_map(|block| block.walk_ops())
+        .flat_map(|op| &op.results)
+        .filter(|val| val.def.is_some())
+        .map(|&val| (val, val.def))
+        .collect();
+
+    for block in region {
+        block.redefine(&defs);
+    }
+}
+
+/// Parse a single block from its textual form.
+///
+/// Value and block names in the text only need to be consistent within it,
+/// they're renumbered when the block is printed again.
+pub fn parse(src: &str) -> ParseResult<Block> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        scopes: vec![Scope::default()],
+    };
+
+    parser.skip_newlines();
+    let mut block = parser.parse_block()?;
+    patch_defs(std::slice::from_mut(&mut block));
+
+    if parser.peek() != TokenKind::Eof {
+        return parser.unexpected("end of input");
+    }
+
+    Ok(block)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::link::LinkedList;
+
+    fn round_trip(src: &str) -> Block {
+        let block = parse(src).unwrap();
+        assert_eq!(block.to_string(), src);
+        block
+    }
+
+    #[test]
+    fn parse_flat_block() {
+        let block = round_trip(
+            "\
+.bb0:
+    %0 := arith.constant {value = 5}
+    %1 := arith.negate %0
+    func.ret %1
+",
+        );
+
+        let ops: Vec<_> = block.iter().collect();
+        assert_eq!(ops.len(), 3);
+        assert_eq!(ops[1].name, "arith.negate");
+        assert_eq!(ops[1].operands, vec![ops[0].get_result()]);
+        assert_eq!(ops[0].get_result().def, *block.head());
+        assert!(matches!(
+            ops[0].attributes.get("value"),
+            Some(Attribute::Int(5, _))
+        ));
+    }
+
+    #[test]
+    fn parse_typed_values() {
+        let block = round_trip(
+            "\
+.bb0:
+    %0: i32 := arith.constant {value = 5}
+    %1: ptr<u8> := test.addr %0
+    %2: fn(i32, ptr<u8>) -> (i64) := test.func
+    %3: !x86.flags := test.cmp %0, %0
+    %4 := test.untyped %1
+",
+        );
+
+        let types: Vec<_> = block
+            .iter()
+            .map(|op| op.get_result().ty().map(|ty| ty.to_string()))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                Some("i32".to_owned()),
+                Some("ptr<u8>".to_owned()),
+                Some("fn(i32, ptr<u8>) -> (i64)".to_owned()),
+                Some("!x86.flags".to_owned()),
+                None
+            ]
+        );
+
+        // uses see the type the value was defined with
+        let ops: Vec<_> = block.iter().collect();
+        assert_eq!(ops[1].operands[0].ty(), Some(Type::int(32, true)));
+
+        let err = parse(".bb0:\n    %0: i := test.x\n").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let block = round_trip(
+            "\
+.bb0:
+    test.op {array = [1, -2 : i8, []], bool = true, dict = {empty = {}, ty = ptr<u8>}, float = 2.5, int = 255 : u8, str = \"say \\\"hi\\\"\\n\", sym = @main}
+",
+        );
+
+        let op = block.iter().next().unwrap();
+        let attr = |name: &str| op.attributes.get(name).unwrap();
+
+        assert_eq!(
+            attr("array"),
+            &Attribute::Array(vec![
+                Attribute::Int(1, Type::int(64, true)),
+                Attribute::Int(-2, Type::int(8, true)),
+                Attribute::Array(Vec::new()),
+            ])
+        );
+        assert_eq!(attr("int"), &Attribute::Int(255, Type::int(8, false)));
+        assert_eq!(attr("float"), &Attribute::Float(2.5));
+        assert_eq!(attr("str"), &Attribute::Str("say \"hi\"\n".to_owned()));
+        assert_eq!(attr("sym"), &Attribute::Symbol("main".to_owned()));
+
+        let Attribute::Dict(dict) = attr("dict") else {
+            panic!("expected a dict");
+        };
+        assert_eq!(
+            dict.get("ty"),
+            Some(&Attribute::Type(Type::ptr(Type::int(8, false))))
+        );
+    }
+
+    #[test]
+    fn round_trip_floats() {
+        let block = round_trip(
+            "\
+.bb0:
+    test.op {big = 1e100, inf = inf, nan = nan, neg_inf = -inf, small = -2.5e-7, zero = -0.0}
+",
+        );
+
+        let op = block.iter().next().unwrap();
+        let float = |name: &str| match op.attributes.get(name) {
+            Some(&Attribute::Float(value)) => value,
+            attr => panic!("expected a float, found {:?}", attr),
+        };
+
+        assert_eq!(float("big"), 1e100);
+        assert_eq!(float("small"), -2.5e-7);
+        assert_eq!(float("inf"), f64::INFINITY);
+        assert_eq!(float("neg_inf"), f64::NEG_INFINITY);
+        assert!(float("nan").is_nan());
+        assert!(float("zero").is_sign_negative());
+
+        assert_eq!(
+            parse(".bb0:\n    test.op {f = 1.5E+3}\n")
+                .unwrap()
+                .to_string(),
+            ".bb0:\n    test.op {f = 1500.0}\n"
+        );
+    }
+
+    #[test]
+    fn reject_invalid_attributes() {
+        let err = parse(".bb0:\n    test.op {int = 256 : u8}\n").unwrap_err();
+        assert_eq!(err.msg, "'256' isn't a valid u8");
+
+        let err = parse(".bb0:\n    test.op {str = \"open}\n").unwrap_err();
+        assert_eq!(err.msg, "unterminated string");
+    }
+
+    #[test]
+    fn parse_nested_blocks() {
+        let block = round_trip(
+            "\
+.bb0:
+    func.func {
+    .bb1:
+        %0 := arith.constant {value = 1}
+        func.ret %0
+    .bb2:
+        x86.ret
+    }
+    func.func {
+    .bb1:
+        %0 := arith.constant {value = 2}
+        func.ret %0
+    }
+",
+        );
+
+        assert_eq!(block.len(), 2);
+
+        // each function is numbered from scratch, but its names are its own
+        let [first, second] = [0, 1].map(|i| &block.iter().nth(i).unwrap().blocks);
+        assert_eq!(first.len(), 2);
+        assert_ne!(first[0].id(), second[0].id());
+        assert_ne!(
+            first[0].iter().next().unwrap().get_result(),
+            second[0].iter().next().unwrap().get_result()
+        );
+    }
+
+    #[test]
+    fn round_trip_names_that_read_as_types() {
+        let block = round_trip(
+            "\
+.bb0:
+    %u8 := test.op {i32 = 1, u8 = u8}
+    test.use %u8
+",
+        );
+
+        let op = block.iter().next().unwrap();
+        assert_eq!(op.get_result().name_hint(), Some("u8"));
+        assert_eq!(
+            op.attributes.get("u8"),
+            Some(&Attribute::Type(Type::int(8, false)))
+        );
+    }
+
+    #[test]
+    fn round_trip_escaped_file_names() {
+        let location = Location::span("say \"hi\"\\.c", 2, 3, 1);
+        assert_eq!(location.to_string(), r#""say \"hi\"\\.c":2:3 to 2:4"#);
+
+        let src = format!(".bb0:\n    test.op loc({})\n", location);
+        let block = parse(&src).unwrap();
+        assert_eq!(block.iter().next().unwrap().location, location);
+        assert_eq!(format!("{:#}", block), src);
+    }
+
+    #[test]
+    fn parse_locations() {
+        let src = "\
+.bb0:
+    %0 := test.const {value = 1} loc(\"main.c\":1:12 to 1:13)
+    test.func {
+    .bb1:
+        test.ret %0 loc(callsite(\"lib.c\":3:5 at fused[\"main.c\":2:1, unknown]))
+    } loc(unknown)
+";
+        let block = parse(src).unwrap();
+        assert_eq!(format!("{:#}", block), src);
+
+        let konst = block.iter().next().unwrap();
+        assert_eq!(konst.location, Location::span("main.c", 1, 12, 1));
+
+        // only printed when asked for
+        assert!(!block.to_string().contains("loc("));
+    }
+
+    #[test]
+    fn keep_name_hints() {
+        let block = round_trip(
+            "\
+.bb0:
+    %x := test.const {value = 1}
+    %0 := test.neg %x
+    %1 := test.add %x, %0
+",
+        );
+
+        let x = block.iter().next().unwrap().get_result();
+        assert_eq!(x.name_hint(), Some("x"));
+        assert_eq!(block.iter().nth(1).unwrap().get_result().name_hint(), None);
+
+        // a name that's taken gets a suffix
+        let mut block = Block::new();
+        for _ in 0..2 {
+            block.push(Operation {
+                results: vec![Value::new(None).with_name("x")],
+                ..Operation::new("test.const")
+            });
+        }
+        assert_eq!(
+            block.to_string(),
+            ".bb0:\n    %x := test.const\n    %x_1 := test.const\n"
+        );
+    }
+
+    #[test]
+    fn parse_multiple_results() {
+        let block = round_trip(
+            "\
+.bb0:
+    %0: i32 := test.const {value = 7}
+    %1: i32, %2 := test.divmod %0, %0
+    test.ret %2, %1
+",
+        );
+
+        let ops: Vec<_> = block.iter().collect();
+        assert_eq!(ops[1].num_results(), 2);
+        assert_eq!(ops[1].result(0).ty(), Some(Type::int(32, true)));
+        assert_eq!(ops[1].result(1).ty(), None);
+        assert_eq!(ops[1].result(1).def, ops[0].ahead);
+        assert_eq!(ops[2].operands, vec![ops[1].result(1), ops[1].result(0)]);
+    }
+
+    #[test]
+    fn parse_block_args_and_successors() {
+        let block = round_trip(
+            "\
+.bb0:
+    test.func {
+    .bb1:
+        %0: i32 := test.const {value = 1}
+        test.cond_br %0, .bb2(%0), .bb3
+    .bb2(%1: i32, %2):
+        test.br .bb3
+    .bb3:
+        test.ret %0
+    }
+",
+        );
+
+        let func = block.iter().next().unwrap();
+        let [entry, then, exit] = func.blocks.as_slice() else {
+            panic!("expected 3 blocks");
+        };
+
+        let br = entry.iter().nth(1).unwrap();
+        assert_eq!(br.operands.len(), 1);
+        assert_eq!(br.successors.len(), 2);
+        assert_eq!(br.successors[0].block, then.id());
+        assert_eq!(br.successors[0].args, br.operands);
+        assert_eq!(br.successors[1].block, exit.id());
+        assert!(br.successors[1].args.is_empty());
+
+        assert_eq!(then.args().len(), 2);
+        assert_eq!(then.args()[0].ty(), Some(Type::int(32, true)));
+        assert_eq!(then.args()[0].def, None);
+        assert_eq!(then.args()[1].ty(), None);
+    }
+
+    #[test]
+    fn reject_operand_after_successor() {
+        let src = "\
+.bb0:
+    test.br .bb1, %0
+";
+        assert_eq!(
+            parse(src).unwrap_err().msg,
+            "expected a successor, but got '%0'"
+        );
+    }
+
+    #[test]
+    fn parse_with_comments_and_blank_lines() {
+        let block = parse(
+            "
+// a comment before the label
+.bb0:
+
+    %a := x86.ax  // trailing comment
+    %a := x86.mov %a, %a
+",
+        )
+        .unwrap();
+
+        assert_eq!(block.len(), 2);
+    }
+
+    #[test]
+    fn report_line_of_error() {
+        let err = parse(".bb0:\n    %0 := arith.constant {value = }\n").unwrap_err();
+        assert_eq!(err.line, 2);
+
+        let err = parse(".bb0:\n    func.func {\n    .bb1:\n        x86.ret\n").unwrap_err();
+        assert_eq!(err.line, 5);
+    }
+}
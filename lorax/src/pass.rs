@@ -0,0 +1,549 @@
+// Run named passes over the IR, in pipelines
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::verify::Diagnostic;
+use crate::{Block, Verifier, WalkOrder, WalkResult, walk_ops_mut};
+
+/// A transformation of the IR that can be put in a pipeline by name.
+pub trait Pass {
+    /// What the pass is called in pipelines and IR dumps, e.g. `x86-lower`
+    fn name(&self) -> &'static str;
+
+    /// Transform the block, or say why it can't be
+    fn run(&self, block: &mut Block) -> Result<(), String>;
+}
+
+#[derive(Debug)]
+pub enum PassError {
+    Failed {
+        pass: &'static str,
+        reason: String,
+    },
+    /// The IR the pass left behind doesn't verify
+    Verifier {
+        pass: &'static str,
+        diagnostics: Vec<Diagnostic>,
+    },
+}
+
+impl Display for PassError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PassError::Failed { pass, reason } => write!(f, "pass {} failed: {}", pass, reason),
+            PassError::Verifier { pass, diagnostics } => {
+                write!(f, "invalid IR after pass {}:", pass)?;
+
+                for diagnostic in diagnostics {
+                    write!(f, "\n    {}", diagnostic)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PipelineError {
+    UnknownPass(String),
+    /// A pass or op name was expected, but there's something else or nothing
+    ExpectedName(Option<char>),
+    /// The nested pipeline for the op isn't closed with `)`
+    Unclosed(String),
+    Unexpected(char),
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineError::UnknownPass(name) => write!(f, "unknown pass {}", name),
+            PipelineError::ExpectedName(Some(c)) => write!(f, "expected a name, found {:?}", c),
+            PipelineError::ExpectedName(None) => write!(f, "expected a name, found the end"),
+            PipelineError::Unclosed(op) => write!(f, "pipeline nested in {} isn't closed", op),
+            PipelineError::Unexpected(c) => write!(f, "unexpected {:?}", c),
+        }
+    }
+}
+
+/// The passes a pipeline can name.
+#[derive(Default)]
+pub struct PassRegistry {
+    passes: HashMap<&'static str, fn() -> Box<dyn Pass>>,
+}
+
+impl PassRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pass(mut self, name: &'static str, make: fn() -> Box<dyn Pass>) -> Self {
+        self.passes.insert(name, make);
+        self
+    }
+
+    /// Everything in both registries, with `other`'s passes winning on clashes
+    pub fn merge(mut self, other: PassRegistry) -> Self {
+        self.passes.extend(other.passes);
+        self
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Box<dyn Pass>> {
+        self.passes.get(name).map(|make| make())
+    }
+}
+
+enum Stage {
+    Pass(Box<dyn Pass>),
+    /// A pipeline run on the blocks of every op with the name, directly in the block
+    Nested(&'static str, Box<PassManager>),
+}
+
+/// Shows the IR under a title, instead of printing it to stderr
+type Dump = Box<dyn Fn(&str, &Block)>;
+
+/// Which passes to dump the IR around, and whether to verify after each.
+///
+/// Only the outermost pass manager's instrumentation is used, nested ones
+/// follow it.
+#[derive(Default)]
+struct Instrumentation {
+    print_before: HashSet<String>,
+    print_after: HashSet<String>,
+    print_before_all: bool,
+    print_after_all: bool,
+    verifier: Option<Verifier>,
+    dump: Option<Dump>,
+}
+
+impl Instrumentation {
+    fn print(&self, when: &str, pass: &str, block: &Block) {
+        let title = format!("IR Dump {} {}", when, pass);
+
+        match &self.dump {
+            Some(dump) => dump(&title, block),
+            None => eprintln!("// -----// {} //----- //\n{}", title, block),
+        }
+    }
+}
+
+/// Runs passes in sequence, and pipelines nested in the ops of the block.
+///
+/// ```ignore
+/// PassManager::new()
+///     .nest("func.func", PassManager::new().pass(Canonicalize))
+///     .pass(x86::Lower)
+///     .print_ir_after_all()
+///     .verify_each(dialect::verifier())
+///     .run(ir)?;
+/// ```
+///
+/// The same pipeline can be parsed from `"func.func(canonicalize),x86-lower"`.
+#[derive(Default)]
+pub struct PassManager {
+    stages: Vec<Stage>,
+    instrumentation: Instrumentation,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.stages.push(Stage::Pass(Box::new(pass)));
+        self
+    }
+
+    /// Run `pipeline` on the blocks of each `op` directly in the block
+    pub fn nest(mut self, op: &'static str, pipeline: PassManager) -> Self {
+        self.stages.push(Stage::Nested(op, Box::new(pipeline)));
+        self
+    }
+
+    /// The pipeline described by `pipeline`: pass names separated by commas,
+    /// where `op(...)` nests a pipeline in the blocks of each `op`
+    pub fn parse(pipeline: &str, registry: &PassRegistry) -> Result<Self, PipelineError> {
+        let mut chars = pipeline.chars().peekable();
+        let manager = parse_stages(&mut chars, registry)?;
+
+        match chars.next() {
+            Some(c) => Err(PipelineError::Unexpected(c)),
+            None => Ok(manager),
+        }
+    }
+
+    pub fn print_ir_before(mut self, pass: &str) -> Self {
+        self.instrumentation.print_before.insert(pass.to_owned());
+        self
+    }
+
+    pub fn print_ir_after(mut self, pass: &str) -> Self {
+        self.instrumentation.print_after.insert(pass.to_owned());
+        self
+    }
+
+    pub fn print_ir_before_all(mut self) -> Self {
+        self.instrumentation.print_before_all = true;
+        self
+    }
+
+    pub fn print_ir_after_all(mut self) -> Self {
+        self.instrumentation.print_after_all = true;
+        self
+    }
+
+    /// Where IR dumps go instead of stderr, given a title like `IR Dump After x86-lower`
+    pub fn dump_with(mut self, dump: impl Fn(&str, &Block) + 'static) -> Self {
+        self.instrumentation.dump = Some(Box::new(dump));
+        self
+    }
+
+    /// Verify the whole IR after each stage of this pipeline. A nested
+    /// pipeline is verified once it ran on every op, as the blocks it runs on
+    /// can refer to symbols outside of them.
+    pub fn verify_each(mut self, verifier: Verifier) -> Self {
+        self.instrumentation.verifier = Some(verifier);
+        self
+    }
+
+    pub fn run(&self, block: &mut Block) -> Result<(), PassError> {
+        self.run_stages(block, &self.instrumentation, true)
+    }
+
+    fn run_stages(
+        &self,
+        block: &mut Block,
+        instrumentation: &Instrumentation,
+        outermost: bool,
+    ) -> Result<(), PassError> {
+        for stage in &self.stages {
+            let name = match stage {
+                Stage::Pass(pass) => {
+                    run_pass(pass.as_ref(), block, instrumentation)?;
+                    pass.name()
+                }
+                Stage::Nested(op, pipeline) => {
+                    let mut result = Ok(());
+
+                    walk_ops_mut(block, WalkOrder::PreOrder, |nested| {
+                        if nested.name == *op {
+                            for nested in &mut nested.blocks {
+                                result = pipeline.run_stages(nested, instrumentation, false);
+
+                                if result.is_err() {
+                                    return WalkResult::Interrupt;
+                                }
+                            }
+                        }
+
+                        // only the ops directly in the block
+                        WalkResult::Skip
+                    });
+
+                    result?;
+                    pipeline.last_pass().unwrap_or(op)
+                }
+            };
+
+            if let (true, Some(verifier)) = (outermost, &instrumentation.verifier) {
+                verifier
+                    .verify(block)
+                    .map_err(|diagnostics| PassError::Verifier {
+                        pass: name,
+                        diagnostics,
+                    })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn last_pass(&self) -> Option<&'static str> {
+        self.stages.iter().rev().find_map(|stage| match stage {
+            Stage::Pass(pass) => Some(pass.name()),
+            Stage::Nested(_, pipeline) => pipeline.last_pass(),
+        })
+    }
+}
+
+fn run_pass(
+    pass: &dyn Pass,
+    block: &mut Block,
+    instrumentation: &Instrumentation,
+) -> Result<(), PassError> {
+    let name = pass.name();
+
+    if instrumentation.print_before_all || instrumentation.print_before.contains(name) {
+        instrumentation.print("Before", name, block);
+    }
+
+    pass.run(block)
+        .map_err(|reason| PassError::Failed { pass: name, reason })?;
+
+    if instrumentation.print_after_all || instrumentation.print_after.contains(name) {
+        instrumentation.print("After", name, block);
+    }
+
+    Ok(())
+}
+
+/// Stages separated by commas, up to the end or a `)`
+fn parse_stages(
+    chars: &mut Peekable<Chars<'_>>,
+    registry: &PassRegistry,
+) -> Result<PassManager, PipelineError> {
+    let mut manager = PassManager::new();
+
+    loop {
+        let name = parse_name(chars)?;
+
+        if chars.next_if_eq(&'(').is_some() {
+            let nested = parse_stages(chars, registry)?;
+
+            if chars.next_if_eq(&')').is_none() {
+                return Err(PipelineError::Unclosed(name));
+            }
+
+            manager = manager.nest(crate::parse::intern(&name), nested);
+        } else {
+            let pass = registry
+                .lookup(&name)
+                .ok_or(PipelineError::UnknownPass(name))?;
+            manager.stages.push(Stage::Pass(pass));
+        }
+
+        skip_whitespace(chars);
+
+        if chars.next_if_eq(&',').is_none() {
+            return Ok(manager);
+        }
+    }
+}
+
+fn parse_name(chars: &mut Peekable<Chars<'_>>) -> Result<String, PipelineError> {
+    skip_whitespace(chars);
+
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || "-_.".contains(c)) {
+        name.push(c);
+    }
+
+    if name.is_empty() {
+        return Err(PipelineError::ExpectedName(chars.peek().copied()));
+    }
+
+    skip_whitespace(chars);
+    Ok(name)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars<'_>>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// The pipeline in the form `parse` reads
+impl Display for PassManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+
+            match stage {
+                Stage::Pass(pass) => write!(f, "{}", pass.name())?,
+                Stage::Nested(op, pipeline) => write!(f, "{}({})", op, pipeline)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::link::LinkedList;
+    use crate::{Value, parse};
+
+    /// Renames every `test.op` directly in the block
+    struct Rename;
+
+    impl Pass for Rename {
+        fn name(&self) -> &'static str {
+            "rename"
+        }
+
+        fn run(&self, block: &mut Block) -> Result<(), String> {
+            walk_ops_mut(block, WalkOrder::PreOrder, |op| {
+                if op.name == "test.op" {
+                    op.name = "test.renamed";
+                }
+                WalkResult::Skip
+            });
+            Ok(())
+        }
+    }
+
+    struct Fail;
+
+    impl Pass for Fail {
+        fn name(&self) -> &'static str {
+            "fail"
+        }
+
+        fn run(&self, _: &mut Block) -> Result<(), String> {
+            Err("on purpose".to_owned())
+        }
+    }
+
+    /// Makes every op directly in the block use a value nothing defines
+    struct Break;
+
+    impl Pass for Break {
+        fn name(&self) -> &'static str {
+            "break"
+        }
+
+        fn run(&self, block: &mut Block) -> Result<(), String> {
+            walk_ops_mut(block, WalkOrder::PreOrder, |op| {
+                op.operands.push(Value::new(None));
+                WalkResult::Skip
+            });
+            Ok(())
+        }
+    }
+
+    fn registry() -> PassRegistry {
+        PassRegistry::new()
+            .pass("rename", || Box::new(Rename))
+            .pass("fail", || Box::new(Fail))
+    }
+
+    #[test]
+    fn parse_pipelines() {
+        let registry = registry();
+
+        for pipeline in [
+            "rename",
+            "rename,rename",
+            "test.func(rename),rename",
+            "a(b(rename))",
+        ] {
+            assert_eq!(
+                PassManager::parse(pipeline, &registry).map(|pm| pm.to_string()),
+                Ok(pipeline.to_owned())
+            );
+        }
+
+        assert_eq!(
+            PassManager::parse(" test.func( rename ) , rename ", &registry)
+                .map(|pm| pm.to_string()),
+            Ok("test.func(rename),rename".to_owned())
+        );
+        assert_eq!(
+            PassManager::parse("rename,regalloc", &registry).err(),
+            Some(PipelineError::UnknownPass("regalloc".to_owned()))
+        );
+        assert_eq!(
+            PassManager::parse("test.func(rename", &registry).err(),
+            Some(PipelineError::Unclosed("test.func".to_owned()))
+        );
+        assert_eq!(
+            PassManager::parse("rename,", &registry).err(),
+            Some(PipelineError::ExpectedName(None))
+        );
+        assert_eq!(
+            PassManager::parse("rename)", &registry).err(),
+            Some(PipelineError::Unexpected(')'))
+        );
+    }
+
+    #[test]
+    fn run_nested_pipelines() {
+        let src = "\
+            .bb0:\n\
+            test.op\n\
+            test.func {\n\
+            .bb1:\n\
+            test.op\n\
+            }\n\
+            test.other {\n\
+            .bb1:\n\
+            test.op\n\
+            }";
+        let mut ir = parse(src).unwrap();
+
+        PassManager::new()
+            .nest("test.func", PassManager::new().pass(Rename))
+            .run(&mut ir)
+            .unwrap();
+
+        assert_eq!(
+            ir.to_string(),
+            ".bb0:\n    test.op\n    test.func {\n    .bb1:\n        test.renamed\n    }\n    \
+             test.other {\n    .bb1:\n        test.op\n    }\n"
+        );
+
+        PassManager::new().pass(Rename).run(&mut ir).unwrap();
+        assert_eq!(ir.iter().next().map(|op| op.name), Some("test.renamed"));
+    }
+
+    #[test]
+    fn dump_ir_around_passes() {
+        let dumps = Rc::new(RefCell::new(Vec::new()));
+        let sink = dumps.clone();
+        let mut ir = parse(".bb0:\ntest.op").unwrap();
+
+        PassManager::parse("rename,rename", &registry())
+            .unwrap()
+            .print_ir_before("rename")
+            .print_ir_after_all()
+            .dump_with(move |title, block| {
+                sink.borrow_mut()
+                    .push(format!("{}: {}", title, block.iter().next().unwrap().name))
+            })
+            .run(&mut ir)
+            .unwrap();
+
+        assert_eq!(
+            *dumps.borrow(),
+            [
+                "IR Dump Before rename: test.op",
+                "IR Dump After rename: test.renamed",
+                "IR Dump Before rename: test.renamed",
+                "IR Dump After rename: test.renamed",
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_at_failures() {
+        let mut ir = parse(".bb0:\ntest.op").unwrap();
+
+        let result = PassManager::parse("fail,rename", &registry())
+            .unwrap()
+            .run(&mut ir);
+        assert!(matches!(
+            result,
+            Err(PassError::Failed { pass: "fail", .. })
+        ));
+        assert_eq!(ir.iter().next().map(|op| op.name), Some("test.op"));
+
+        let result = PassManager::new()
+            .pass(Rename)
+            .pass(Break)
+            .pass(Rename)
+            .verify_each(Verifier::new())
+            .run(&mut ir);
+        assert!(matches!(
+            result,
+            Err(PassError::Verifier { pass: "break", .. })
+        ));
+    }
+}
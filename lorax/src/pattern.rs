@@ -0,0 +1,443 @@
+// Declarative rewrite rules
+
+use crate::{Operation, RewritingCtx, Value};
+
+/// What a matched op can be replaced with.
+pub trait Replacement {
+    fn replace(self, ctx: &mut RewritingCtx<'_>);
+}
+
+/// Whether the matched op's results from `skip` on are unused, so nothing
+/// would be left referring to them once it's gone. Otherwise the rewrite is
+/// given up, taking out the ops it inserted.
+fn drop_results(ctx: &mut RewritingCtx<'_>, skip: usize) -> bool {
+    let used = ctx
+        .results()
+        .iter()
+        .skip(skip)
+        .any(|&val| ctx.num_uses(val) > 0);
+    if used {
+        ctx.discard_created();
+    }
+
+    !used
+}
+
+/// The op takes the matched op's place. If both have as many results, the
+/// matched op's results are kept so their users stay valid. Otherwise the
+/// match fails, unless none of them are used.
+impl Replacement for Operation {
+    fn replace(mut self, ctx: &mut RewritingCtx<'_>) {
+        if self.results.len() == ctx.results().len() {
+            self.results.copy_from_slice(ctx.results());
+        } else if !drop_results(ctx, 0) {
+            return;
+        }
+
+        ctx.replace(self);
+    }
+}
+
+/// The matched op is erased, and users of its result use the value instead.
+/// The match fails if it has other results still in use.
+impl Replacement for Value {
+    fn replace(self, ctx: &mut RewritingCtx<'_>) {
+        if !drop_results(ctx, 1) {
+            return;
+        }
+
+        if let Some(old) = ctx.result() {
+            ctx.replace_all_uses_with(old, self);
+        }
+
+        ctx.erase();
+    }
+}
+
+/// Define rewrite rules from a source pattern and what to replace it with.
+///
+/// ```ignore
+/// rewrite_pattern! {
+///     /// -(-x) is x
+///     pub struct DoubleNegate: arith.negate(arith.negate(x)) => { x }
+///
+///     pub struct LowerConst: arith.constant() { value }, benefit = 2 => {
+///         imm(value)
+///     }
+///
+///     pub struct LowerNegate: arith.negate(src) -> dst => {
+///         let tmp = r10();
+///         mov(src, tmp);
+///         neg(tmp);
+///         mov(tmp, dst)
+///     }
+/// }
+/// ```
+///
+/// A pattern names the root op and lists its operands, each either a binding,
+/// `_`, or a nested pattern the operand's defining op has to match. Attributes
+/// are matched against Rust patterns in braces, or bound by name to their value
+/// converted with `AttrValue`, `-> name` binds an op's result,
+/// and an `if` guard can check the bindings further.
+///
+/// In the replacement, `let name = op;` inserts `op` before the matched one and
+/// binds its result, `op;` inserts it without binding anything, and the final
+/// expression is the `Replacement` for the matched op.
+#[macro_export]
+macro_rules! rewrite_pattern {
+    ($(
+        $(#[$meta:meta])*
+        $vis:vis struct $rule:ident :
+            $dl:ident . $op:ident ( $($operands:tt)* )
+            $({ $($attrs:tt)* })?
+            $(-> $result:ident)?
+            $(if $guard:expr)?
+            $(, benefit = $benefit:literal)?
+        => { $($replacement:tt)* }
+    )*) => {$(
+        $(#[$meta])*
+        $vis struct $rule;
+
+        impl<'block> $crate::RewriteRule<$crate::RewritingCtx<'block>> for $rule {
+            fn roots(&self) -> Option<&'static [&'static str]> {
+                Some(&[concat!(stringify!($dl), ".", stringify!($op))])
+            }
+
+            fn benefit(&self) -> u16 {
+                $crate::rewrite_pattern!(@benefit $($benefit)?)
+            }
+
+            fn apply(&self, ctx: &mut $crate::RewritingCtx<'block>) {
+                if ctx.is_erased() || ctx.name() != concat!(stringify!($dl), ".", stringify!($op)) {
+                    return;
+                }
+                let root = ctx.get();
+
+                $crate::rewrite_pattern!(@op ctx, root, ($($operands)*) $({ $($attrs)* })? $(-> $result)?);
+
+                $(
+                    if !($guard) {
+                        return;
+                    }
+                )?
+
+                $crate::rewrite_pattern!(@replace ctx, $($replacement)*);
+            }
+        }
+    )*};
+
+    (@benefit) => { 1 };
+    (@benefit $benefit:literal) => { $benefit };
+
+    // the parts of an op other than its name, which was already checked
+    (@op $ctx:ident, $op:ident, ($($operands:tt)*) $({ $($attr:ident $(: $pat:pat)?),* $(,)? })? $(-> $result:ident)?) => {
+        let mut operands = $op.operands.iter().copied();
+        $crate::rewrite_pattern!(@operands $ctx, operands, $($operands)*);
+        if operands.next().is_some() {
+            return;
+        }
+
+        $($(
+            $crate::rewrite_pattern!(@attr $op, $attr $(: $pat)?);
+        )*)?
+
+        $(
+            let Some(&$result) = $op.results.first() else {
+                return;
+            };
+        )?
+    };
+
+    // a bare name binds the attribute's value, converted to whatever type it's used as
+    (@attr $op:ident, $attr:ident) => {
+        let Some($attr) = $op
+            .attributes
+            .get(stringify!($attr))
+            .and_then($crate::attr::AttrValue::from_attr)
+        else {
+            return;
+        };
+    };
+
+    (@attr $op:ident, $attr:ident : $pat:pat) => {
+        let Some($pat) = $op.attributes.get(stringify!($attr)).cloned() else {
+            return;
+        };
+    };
+
+    (@operands $ctx:ident, $iter:ident, $(,)?) => {};
+
+    (@operands $ctx:ident, $iter:ident,
+        $dl:ident . $name:ident ( $($operands:tt)* ) $({ $($attrs:tt)* })? $(-> $result:ident)?
+        $(, $($rest:tt)*)?
+    ) => {
+        let Some(def) = $iter.next().and_then(|val| $ctx.def_of(val)) else {
+            return;
+        };
+        if def.name != concat!(stringify!($dl), ".", stringify!($name)) {
+            return;
+        }
+        $crate::rewrite_pattern!(@op $ctx, def, ($($operands)*) $({ $($attrs)* })? $(-> $result)?);
+
+        $crate::rewrite_pattern!(@operands $ctx, $iter, $($($rest)*)?);
+    };
+
+    (@operands $ctx:ident, $iter:ident, _ $(, $($rest:tt)*)?) => {
+        if $iter.next().is_none() {
+            return;
+        }
+
+        $crate::rewrite_pattern!(@operands $ctx, $iter, $($($rest)*)?);
+    };
+
+    (@operands $ctx:ident, $iter:ident, $bind:ident $(, $($rest:tt)*)?) => {
+        let Some($bind) = $iter.next() else {
+            return;
+        };
+
+        $crate::rewrite_pattern!(@operands $ctx, $iter, $($($rest)*)?);
+    };
+
+    (@replace $ctx:ident, let $bind:ident = $op:expr; $($rest:tt)*) => {
+        let $bind = $ctx.insert_behind($op);
+        let $bind = $ctx
+            .deref($bind)
+            .results
+            .first()
+            .copied()
+            .expect("ops bound in a replacement should have a result");
+
+        $crate::rewrite_pattern!(@replace $ctx, $($rest)*);
+    };
+
+    (@replace $ctx:ident, $op:expr; $($rest:tt)+) => {
+        $ctx.insert_behind($op);
+
+        $crate::rewrite_pattern!(@replace $ctx, $($rest)+);
+    };
+
+    (@replace $ctx:ident, $replacement:expr $(;)?) => {
+        $crate::Replacement::replace($replacement, $ctx);
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::attr::Attribute;
+    use crate::link::LinkedList;
+    use crate::{Block, GreedyConfig, RewriteRule, RewriteRuleSet, parse, rewrite_greedily};
+
+    fn op(name: &'static str, operands: Vec<Value>, result: Option<Value>) -> Operation {
+        Operation {
+            operands,
+            results: result.into_iter().collect(),
+            ..Operation::new(name)
+        }
+    }
+
+    fn scratch() -> Operation {
+        op("test.scratch", Vec::new(), Some(Value::new(None)))
+    }
+
+    fn copy(src: Value, dst: Value) -> Operation {
+        op("test.copy", vec![src, dst], Some(dst))
+    }
+
+    fn zero() -> Operation {
+        op("test.zero", Vec::new(), Some(Value::new(None)))
+    }
+
+    rewrite_pattern! {
+        struct DoubleNeg: test.neg(test.neg(x)) => { x }
+
+        struct ZeroConst: test.const() { value: Attribute::Int(value, _) } if value == 0, benefit = 2 => {
+            zero()
+        }
+
+        struct SplitConst: test.const() { value: Attribute::Int(1, _) } => {
+            scratch();
+            op("test.split", Vec::new(), None)
+        }
+
+        struct NegInPlace: test.neg(src) -> dst => {
+            let tmp = scratch();
+            copy(src, tmp);
+            op("test.neg_in_place", vec![tmp], Some(tmp));
+            copy(tmp, dst)
+        }
+    }
+
+    fn rewrite(src: &str, rule: impl for<'a> RewriteRule<RewritingCtx<'a>> + 'static) -> Block {
+        let mut block = parse(src).unwrap();
+        let rules = RewriteRuleSet::new().add_rule(rule);
+        rewrite_greedily(&mut block, rules, GreedyConfig::default());
+        block
+    }
+
+    fn names(block: &Block) -> Vec<&'static str> {
+        block.iter().map(|op| op.name).collect()
+    }
+
+    #[test]
+    fn match_nested_defining_op() {
+        let block = rewrite(
+            "\
+.bb0:
+    %0 := test.const {value = 1}
+    %1 := test.neg %0
+    %2 := test.neg %1
+    test.ret %2
+",
+            DoubleNeg,
+        );
+
+        assert_eq!(names(&block), vec!["test.const", "test.neg", "test.ret"]);
+
+        let ops: Vec<_> = block.iter().collect();
+        assert_eq!(ops[2].operands, vec![ops[0].get_result()]);
+    }
+
+    #[test]
+    fn match_attributes_and_guard() {
+        let block = rewrite(
+            "\
+.bb0:
+    %0 := test.const {value = 0}
+    %1 := test.const {value = 1}
+    test.ret %0
+",
+            ZeroConst,
+        );
+
+        assert_eq!(names(&block), vec!["test.zero", "test.const", "test.ret"]);
+        assert_eq!(ZeroConst.benefit(), 2);
+
+        // the replacement keeps the matched op's result
+        let ops: Vec<_> = block.iter().collect();
+        assert_eq!(ops[2].operands, vec![ops[0].get_result()]);
+    }
+
+    #[test]
+    fn insert_replacement_ops() {
+        let block = rewrite(
+            "\
+.bb0:
+    %0 := test.const {value = 1}
+    %1 := test.neg %0
+    test.ret %1
+",
+            NegInPlace,
+        );
+
+        assert_eq!(
+            names(&block),
+            vec![
+                "test.const",
+                "test.scratch",
+                "test.copy",
+                "test.neg_in_place",
+                "test.copy",
+                "test.ret"
+            ]
+        );
+        assert_eq!(crate::verify(&block), Ok(()));
+    }
+
+    #[test]
+    fn operand_count_must_match() {
+        let block = rewrite(
+            "\
+.bb0:
+    %0 := test.const {value = 1}
+    %1 := test.neg %0, %0
+",
+            NegInPlace,
+        );
+
+        assert_eq!(names(&block), vec!["test.const", "test.neg"]);
+    }
+
+    #[test]
+    fn replace_uses_in_other_blocks() {
+        let mut block = parse(
+            "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const {value = 1}
+        %1 := test.neg %0
+        %2 := test.neg %1
+        test.br .bb2
+    .bb2:
+        test.ret %2
+    }
+",
+        )
+        .unwrap();
+        rewrite_greedily(
+            &mut block,
+            RewriteRuleSet::new().add_rule(DoubleNeg),
+            GreedyConfig::default(),
+        );
+
+        let region = &block.iter().next().unwrap().blocks;
+        let konst = region[0].iter().next().unwrap().get_result();
+        let ret = region[1].iter().next().unwrap();
+
+        // the use in .bb2 follows the value the erased op was replaced with
+        assert_eq!(ret.operands, vec![konst]);
+        assert_eq!(Block::num_uses_in(region, konst), 2);
+        assert_eq!(crate::verify(&block), Ok(()));
+    }
+
+    #[test]
+    fn rules_check_the_root_name() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const {value = 1}
+    %1 := test.neg %0
+",
+        )
+        .unwrap();
+        let head = block.head().unwrap();
+
+        // applied by hand to an op it doesn't match
+        let mut ctx = RewritingCtx::new(&mut block, head);
+        NegInPlace.apply(&mut ctx);
+        ctx.release();
+
+        assert_eq!(names(&block), vec!["test.const", "test.neg"]);
+    }
+
+    #[test]
+    fn result_count_must_match_if_used() {
+        let block = rewrite(
+            "\
+.bb0:
+    %0 := test.const {value = 1}
+    test.ret %0
+",
+            SplitConst,
+        );
+
+        // the inserted op is taken out again, and the use keeps its def
+        assert_eq!(names(&block), vec!["test.const", "test.ret"]);
+        assert_eq!(crate::verify(&block), Ok(()));
+
+        let block = rewrite(
+            "\
+.bb0:
+    %0 := test.const {value = 1}
+    test.ret
+",
+            SplitConst,
+        );
+
+        assert_eq!(
+            names(&block),
+            vec!["test.scratch", "test.split", "test.ret"]
+        );
+    }
+}
@@ -1,67 +1,343 @@
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// A handle to an object in a `Pool`.
+///
+/// The generation tells apart objects that have lived in the same slot,
+/// so a `Ptr` kept around after its object was freed is caught instead of
+/// silently referring to whatever took its place.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd)]
 pub struct Ptr {
     pub(crate) idx: usize,
+    pub(crate) generation: u32,
 }
 
 impl Ptr {
     pub fn new(idx: usize) -> Self {
-        Self { idx }
+        Self { idx, generation: 0 }
     }
 }
 
 impl From<usize> for Ptr {
     fn from(idx: usize) -> Self {
-        Self { idx }
+        Self::new(idx)
+    }
+}
+
+impl Display for Ptr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}@{}", self.idx, self.generation)
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    generation: u32,
+    obj: Option<T>,
+}
+
+/// Where `Pool::compact` moved each live object.
+#[derive(Debug, Default)]
+pub struct Remap {
+    moved: HashMap<Ptr, Ptr>,
+}
+
+impl Remap {
+    /// The new location of the object `old` pointed to, if it was live
+    pub fn get(&self, old: Ptr) -> Option<Ptr> {
+        self.moved.get(&old).copied()
+    }
+
+    /// Update a pointer in place. Pointers that were already dangling are left alone.
+    pub fn apply(&self, ptr: &mut Ptr) {
+        if let Some(new) = self.get(*ptr) {
+            *ptr = new;
+        }
+    }
+
+    pub fn apply_opt(&self, ptr: &mut Option<Ptr>) {
+        if let Some(ptr) = ptr {
+            self.apply(ptr);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.moved.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty()
     }
 }
 
+/// Storage for objects that refer to each other through `Ptr`s.
+///
+/// Freed slots are left empty and handed out again by later allocations.
 #[derive(Debug)]
 pub struct Pool<T> {
-    objs: Vec<T>,
+    objs: Vec<Slot<T>>,
+    free: Vec<usize>,
 }
 
-impl<'a, T> Pool<T> {
+impl<T> Pool<T> {
     pub fn new() -> Self {
-        Pool { objs: Vec::new() }
+        Pool {
+            objs: Vec::new(),
+            free: Vec::new(),
+        }
     }
 
     pub fn reserve(&mut self, count: usize) {
-        self.objs.reserve(count);
+        self.objs.reserve(count.saturating_sub(self.free.len()));
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         Pool {
             objs: Vec::with_capacity(capacity),
+            free: Vec::new(),
         }
     }
 
     pub fn alloc(&mut self, obj: T) -> Ptr {
-        self.objs.push(obj);
+        if let Some(idx) = self.free.pop() {
+            let slot = &mut self.objs[idx];
+            slot.obj = Some(obj);
 
-        Ptr {
-            idx: self.objs.len() - 1,
+            return Ptr {
+                idx,
+                generation: slot.generation,
+            };
         }
+
+        self.objs.push(Slot {
+            generation: 0,
+            obj: Some(obj),
+        });
+
+        Ptr::new(self.objs.len() - 1)
     }
 
-    pub fn deref(&self, ptr: Ptr) -> &T {
-        self.objs.get(ptr.idx).expect("Deref of dangling ptr")
+    /// Remove the object at `ptr`, leaving its slot for reuse
+    pub fn free(&mut self, ptr: Ptr) -> T {
+        let Some(obj) = self.slot_mut(ptr).and_then(|slot| slot.obj.take()) else {
+            panic!("Free of dangling ptr: {}", self.why_dangling(ptr));
+        };
+
+        // invalidate every ptr to the old object
+        let slot = &mut self.objs[ptr.idx];
+        slot.generation = slot.generation.wrapping_add(1);
+
+        self.free.push(ptr.idx);
+        obj
     }
 
-    pub fn deref_mut(&mut self, ptr: Ptr) -> &mut T {
+    fn slot_mut(&mut self, ptr: Ptr) -> Option<&mut Slot<T>> {
         self.objs
             .get_mut(ptr.idx)
-            .expect("Mut deref of dangling ptr")
+            .filter(|slot| slot.generation == ptr.generation)
+    }
+
+    pub fn get(&self, ptr: Ptr) -> Option<&T> {
+        self.objs
+            .get(ptr.idx)
+            .filter(|slot| slot.generation == ptr.generation)
+            .and_then(|slot| slot.obj.as_ref())
+    }
+
+    pub fn get_mut(&mut self, ptr: Ptr) -> Option<&mut T> {
+        self.slot_mut(ptr).and_then(|slot| slot.obj.as_mut())
+    }
+
+    /// The live object in the slot `ptr` points at, even if `ptr` itself is stale
+    pub(crate) fn occupant(&self, ptr: Ptr) -> Option<&T> {
+        self.objs.get(ptr.idx).and_then(|slot| slot.obj.as_ref())
+    }
+
+    /// Explain why `ptr` can't be dereferenced
+    pub fn why_dangling(&self, ptr: Ptr) -> String {
+        match self.objs.get(ptr.idx) {
+            None => format!("{} is out of range", ptr),
+            Some(slot) if slot.generation != ptr.generation => format!(
+                "{} is stale, its slot has moved on to generation {}",
+                ptr, slot.generation
+            ),
+            Some(_) => format!("{} points at a freed slot", ptr),
+        }
+    }
+
+    pub fn deref(&self, ptr: Ptr) -> &T {
+        match self.get(ptr) {
+            Some(obj) => obj,
+            None => panic!("Deref of dangling ptr: {}", self.why_dangling(ptr)),
+        }
+    }
+
+    pub fn deref_mut(&mut self, ptr: Ptr) -> &mut T {
+        if self.get(ptr).is_none() {
+            panic!("Mut deref of dangling ptr: {}", self.why_dangling(ptr));
+        }
+
+        self.get_mut(ptr).expect("ptr was just checked")
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &T> {
-        self.objs.iter()
+        self.objs.iter().filter_map(|slot| slot.obj.as_ref())
     }
 
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
-        self.objs.iter_mut()
+        self.objs.iter_mut().filter_map(|slot| slot.obj.as_mut())
+    }
+
+    /// Every live object along with a pointer to it, in storage order
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = (Ptr, &mut T)> {
+        self.objs.iter_mut().enumerate().filter_map(|(idx, slot)| {
+            let generation = slot.generation;
+            slot.obj.as_mut().map(|obj| (Ptr { idx, generation }, obj))
+        })
     }
 
+    /// Pointers to every live object, in storage order
+    pub fn ptrs(&self) -> impl Iterator<Item = Ptr> + '_ {
+        (0..self.objs.len()).filter_map(|idx| self.ptr_at(idx))
+    }
+
+    /// A pointer to the live object at slot `idx`, if there is one
+    pub(crate) fn ptr_at(&self, idx: usize) -> Option<Ptr> {
+        let slot = self.objs.get(idx)?;
+
+        slot.obj.as_ref().map(|_| Ptr {
+            idx,
+            generation: slot.generation,
+        })
+    }
+
+    /// Number of live objects
     pub fn len(&self) -> usize {
+        self.objs.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of slots, live or free. Every `Ptr` from this pool indexes below it.
+    pub(crate) fn slots(&self) -> usize {
         self.objs.len()
     }
+
+    /// Move live objects together, dropping free slots.
+    ///
+    /// Every existing `Ptr` into the pool is invalidated, the returned
+    /// `Remap` says where each live object ended up.
+    pub fn compact(&mut self) -> Remap {
+        // newer than any generation handed out so far
+        let generation = self
+            .objs
+            .iter()
+            .map(|slot| slot.generation)
+            .max()
+            .map_or(0, |max| max.wrapping_add(1));
+
+        let mut remap = Remap::default();
+
+        for (idx, slot) in std::mem::take(&mut self.objs).into_iter().enumerate() {
+            let Some(obj) = slot.obj else {
+                continue;
+            };
+
+            let old = Ptr {
+                idx,
+                generation: slot.generation,
+            };
+            let new = Ptr {
+                idx: self.objs.len(),
+                generation,
+            };
+
+            remap.moved.insert(old, new);
+            self.objs.push(Slot {
+                generation,
+                obj: Some(obj),
+            });
+        }
+
+        self.free.clear();
+
+        remap
+    }
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn free_slots_are_reused() {
+        let mut pool = Pool::new();
+        let a = pool.alloc('a');
+        let b = pool.alloc('b');
+        let c = pool.alloc('c');
+
+        assert_eq!(pool.free(b), 'b');
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.get(b), None);
+        assert_eq!(pool.iter().collect::<String>(), "ac");
+        assert_eq!(pool.ptrs().collect::<Vec<_>>(), vec![a, c]);
+
+        let d = pool.alloc('d');
+        assert_eq!(d.idx, b.idx);
+        assert_ne!(d, b);
+        assert_eq!(pool.len(), 3);
+        assert_eq!(pool.iter().collect::<String>(), "adc");
+    }
+
+    #[test]
+    #[should_panic(expected = "Deref of dangling ptr")]
+    fn deref_freed_ptr() {
+        let mut pool = Pool::new();
+        let a = pool.alloc(0);
+        pool.free(a);
+        pool.deref(a);
+    }
+
+    #[test]
+    #[should_panic(expected = "is stale")]
+    fn deref_stale_ptr() {
+        let mut pool = Pool::new();
+        let a = pool.alloc(0);
+        pool.free(a);
+
+        // `b` takes over `a`'s slot
+        let b = pool.alloc(1);
+        assert_eq!(*pool.deref(b), 1);
+
+        pool.deref(a);
+    }
+
+    #[test]
+    fn compact_remaps_live_objects() {
+        let mut pool = Pool::new();
+        let ptrs: Vec<_> = "abcde".chars().map(|c| pool.alloc(c)).collect();
+        pool.free(ptrs[0]);
+        pool.free(ptrs[3]);
+
+        let remap = pool.compact();
+
+        assert_eq!(pool.slots(), 3);
+        assert_eq!(remap.len(), 3);
+        assert_eq!(remap.get(ptrs[0]), None);
+
+        for (old, c) in [(ptrs[1], 'b'), (ptrs[2], 'c'), (ptrs[4], 'e')] {
+            let new = remap.get(old).unwrap();
+            assert_eq!(*pool.deref(new), c);
+            // the old ptr doesn't alias whatever moved into its slot
+            assert_eq!(pool.get(old), None);
+        }
+    }
 }
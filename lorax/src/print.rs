@@ -0,0 +1,230 @@
+// Print the textual form of the IR, as read by `parse`
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter, Result};
+
+use crate::link::LinkedList;
+use crate::{Block, Operation, Value};
+
+const INDENT: &str = "    ";
+
+/// The names values and blocks are printed with.
+///
+/// Values are numbered densely in the order they're first printed, unless they
+/// have a name hint that's still free. Blocks are numbered in the order of
+/// their regions.
+#[derive(Clone, Default)]
+struct Names {
+    values: HashMap<Value, String>,
+    taken: HashSet<String>,
+    next_value: usize,
+
+    blocks: HashMap<usize, usize>,
+    next_block: usize,
+}
+
+impl Names {
+    fn value(&mut self, val: Value) -> String {
+        if let Some(name) = self.values.get(&val) {
+            return format!("%{}", name);
+        }
+
+        let name = match val.name_hint() {
+            Some(hint) if !self.taken.contains(hint) => hint.to_owned(),
+            Some(hint) => (1..)
+                .map(|n| format!("{}_{}", hint, n))
+                .find(|name| !self.taken.contains(name))
+                .expect("there are more names than values"),
+            None => loop {
+                let name = self.next_value.to_string();
+                self.next_value += 1;
+
+                if !self.taken.contains(&name) {
+                    break name;
+                }
+            },
+        };
+
+        self.taken.insert(name.clone());
+        self.values.insert(val, name.clone());
+        format!("%{}", name)
+    }
+
+    fn block(&mut self, id: usize) -> usize {
+        let next = self.next_block;
+        let label = *self.blocks.entry(id).or_insert(next);
+
+        if label == next {
+            self.next_block += 1;
+        }
+
+        label
+    }
+
+    /// The value, with its type if it has one, like `%0: i32`
+    fn typed_value(&mut self, val: Value) -> String {
+        let name = self.value(val);
+
+        match val.ty() {
+            Some(ty) => format!("{}: {}", name, ty),
+            None => name,
+        }
+    }
+}
+
+fn fmt_list(f: &mut Formatter<'_>, list: impl IntoIterator<Item = String>) -> Result {
+    for (i, item) in list.into_iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+
+    Ok(())
+}
+
+struct Printer<'a, 'f> {
+    f: &'a mut Formatter<'f>,
+    names: Names,
+    locations: bool,
+}
+
+impl Printer<'_, '_> {
+    /// Print the block, with its ops indented one level deeper than `depth`.
+    ///
+    /// Values and blocks nested in the ops of the outermost block are
+    /// numbered afresh for each op, so that one function's numbering doesn't
+    /// depend on the functions before it.
+    fn block(&mut self, block: &Block, depth: usize, outermost: bool) -> Result {
+        let label = self.names.block(block.id());
+        write!(self.f, "{}.bb{}", INDENT.repeat(depth), label)?;
+
+        if !block.args().is_empty() {
+            let args: Vec<_> = block
+                .args()
+                .iter()
+                .map(|&arg| self.names.typed_value(arg))
+                .collect();
+
+            write!(self.f, "(")?;
+            fmt_list(self.f, args)?;
+            write!(self.f, ")")?;
+        }
+
+        writeln!(self.f, ":")?;
+
+        for op in block.iter() {
+            write!(self.f, "{}", INDENT.repeat(depth + 1))?;
+            self.op(op, depth + 1, outermost)?;
+            writeln!(self.f)?;
+        }
+
+        Ok(())
+    }
+
+    fn op(&mut self, op: &Operation, depth: usize, renumber: bool) -> Result {
+        if !op.results.is_empty() {
+            let results: Vec<_> = op
+                .results
+                .iter()
+                .map(|&val| self.names.typed_value(val))
+                .collect();
+
+            fmt_list(self.f, results)?;
+            write!(self.f, " := ")?;
+        }
+
+        write!(self.f, "{}", op.name)?;
+
+        if !op.operands.is_empty() || !op.successors.is_empty() {
+            let mut operands: Vec<_> = op
+                .operands
+                .iter()
+                .map(|&val| self.names.value(val))
+                .collect();
+
+            for successor in &op.successors {
+                let mut label = format!(".bb{}", self.names.block(successor.block));
+
+                if !successor.args.is_empty() {
+                    let args: Vec<_> = successor
+                        .args
+                        .iter()
+                        .map(|&val| self.names.value(val))
+                        .collect();
+                    label = format!("{}({})", label, args.join(", "));
+                }
+
+                operands.push(label);
+            }
+
+            write!(self.f, " ")?;
+            fmt_list(self.f, operands)?;
+        }
+
+        if !op.attributes.is_empty() {
+            write!(self.f, " {{")?;
+            fmt_list(
+                self.f,
+                op.attributes
+                    .iter()
+                    .map(|(key, attr)| format!("{} = {}", key, attr)),
+            )?;
+            write!(self.f, "}}")?;
+        }
+
+        if !op.blocks.is_empty() {
+            let outer = renumber.then(|| self.names.clone());
+
+            // numbered up front, so branches to blocks further down agree with their labels
+            for block in &op.blocks {
+                self.names.block(block.id());
+            }
+
+            writeln!(self.f, " {{")?;
+
+            for block in &op.blocks {
+                self.block(block, depth, false)?;
+            }
+
+            write!(self.f, "{}}}", INDENT.repeat(depth))?;
+
+            if let Some(outer) = outer {
+                self.names = outer;
+            }
+        }
+
+        if self.locations {
+            write!(self.f, " loc({})", op.location)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Printed with `{:#}`, each op is followed by its location.
+impl Display for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let locations = f.alternate();
+
+        Printer {
+            f,
+            names: Names::default(),
+            locations,
+        }
+        .block(self, 0, true)
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let locations = f.alternate();
+
+        Printer {
+            f,
+            names: Names::default(),
+            locations,
+        }
+        .op(self, 0, false)
+    }
+}
@@ -0,0 +1,119 @@
+// Rewrite one tree into another, node by node
+
+/// A node that can be rewritten into a `To`, e.g. an AST node into the
+/// instructions it lowers to, or an instruction into its assembly.
+pub trait Rewritable<To> {
+    fn rewrite(&self) -> To;
+}
+
+/// Rewrite `from` into a `To`, which is usually inferred from where the result goes.
+pub fn rewrite<From, To>(from: &From) -> To
+where
+    From: Rewritable<To> + ?Sized,
+{
+    from.rewrite()
+}
+
+impl<From, To> Rewritable<To> for Box<From>
+where
+    From: Rewritable<To> + ?Sized,
+{
+    fn rewrite(&self) -> To {
+        self.as_ref().rewrite()
+    }
+}
+
+impl<From, To> Rewritable<Vec<To>> for [From]
+where
+    From: Rewritable<To>,
+{
+    fn rewrite(&self) -> Vec<To> {
+        self.iter().map(From::rewrite).collect()
+    }
+}
+
+impl<From, To> Rewritable<Vec<To>> for Vec<From>
+where
+    From: Rewritable<To>,
+{
+    fn rewrite(&self) -> Vec<To> {
+        self.as_slice().rewrite()
+    }
+}
+
+impl<From, To> Rewritable<Option<To>> for Option<From>
+where
+    From: Rewritable<To>,
+{
+    fn rewrite(&self) -> Option<To> {
+        self.as_ref().map(From::rewrite)
+    }
+}
+
+/// Implement `Rewritable` with a match on the node being rewritten.
+///
+/// ```ignore
+/// rewrite_rule! {
+///     Operand => String {
+///         Operand::Imm(value) => format!("${value}"),
+///         Operand::Register => "%eax".to_owned(),
+///     }
+/// }
+/// ```
+///
+/// The arms match on `&self`, so bindings are references. `Rewritable` has to be in scope.
+#[macro_export]
+macro_rules! rewrite_rule {
+    ($from:ty => $to:ty { $($arms:tt)* }) => {
+        impl Rewritable<$to> for $from {
+            fn rewrite(&self) -> $to {
+                match self {
+                    $($arms)*
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    enum Expr {
+        Num(u32),
+        Neg(Box<Expr>),
+        Sum(Vec<Expr>),
+    }
+
+    rewrite_rule! {
+        Expr => String {
+            Expr::Num(value) => value.to_string(),
+            Expr::Neg(expr) => format!("-{}", rewrite::<_, String>(expr)),
+            Expr::Sum(exprs) => {
+                let exprs: Vec<String> = rewrite(exprs);
+                format!("({})", exprs.join(" + "))
+            }
+        }
+    }
+
+    rewrite_rule! {
+        Expr => i64 {
+            Expr::Num(value) => i64::from(*value),
+            Expr::Neg(expr) => -rewrite::<_, i64>(expr),
+            Expr::Sum(exprs) => rewrite::<_, Vec<i64>>(exprs).into_iter().sum(),
+        }
+    }
+
+    #[test]
+    fn rewrite_into_each_target() {
+        let expr = Expr::Sum(vec![
+            Expr::Num(1),
+            Expr::Neg(Box::new(Expr::Num(2))),
+            Expr::Neg(Box::new(Expr::Sum(vec![Expr::Num(3)]))),
+        ]);
+
+        assert_eq!(rewrite::<_, String>(&expr), "(1 + -2 + -(3))");
+        assert_eq!(rewrite::<_, i64>(&expr), -4);
+        assert_eq!(rewrite::<_, Option<i64>>(&Some(expr)), Some(-4));
+    }
+}
@@ -1,27 +1,211 @@
+use std::collections::HashMap;
+
 pub trait RewriteRule<T> {
     fn apply(&self, node: &mut T);
+
+    /// Names of the ops this rule can match, or `None` if it can match any op.
+    ///
+    /// A rule set only tries the rule on ops with one of these names, so the
+    /// rule itself doesn't need to check.
+    fn roots(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
+    /// Rules with a higher benefit are tried first
+    fn benefit(&self) -> u16 {
+        1
+    }
+}
+
+/// What a rule set is applied to: a position in the IR, rooted at a named op.
+pub trait RewriteTarget {
+    fn root_name(&self) -> &'static str;
+
+    /// Whether a rule changed the IR since the target moved to its current op
+    fn changed(&self) -> bool;
 }
 
-/// A collection of rewrite rules, applied in a specific order.
+/// A collection of rewrite rules, indexed by the ops they root on.
+///
+/// Rules are tried from highest to lowest benefit, ties going to the rule added
+/// first, until one of them changes the IR.
 pub struct RewriteRuleSet<T> {
     rules: Vec<Box<dyn RewriteRule<T>>>,
+
+    // indices into `rules`, in the order they're tried
+    by_root: HashMap<&'static str, Vec<usize>>,
+    any_root: Vec<usize>,
 }
 
 impl<T> RewriteRuleSet<T> {
     pub fn new() -> Self {
-        Self { rules: Vec::new() }
+        Self {
+            rules: Vec::new(),
+            by_root: HashMap::new(),
+            any_root: Vec::new(),
+        }
     }
 
     pub fn add_rule<R: RewriteRule<T> + 'static>(mut self, rule: R) -> Self {
+        let idx = self.rules.len();
+
+        match rule.roots() {
+            Some(roots) => {
+                for &root in roots {
+                    self.by_root.entry(root).or_default().push(idx);
+                }
+            }
+            None => self.any_root.push(idx),
+        }
+
         self.rules.push(Box::new(rule));
+
+        // stable, so ties stay in the order rules were added
+        let benefit = |&idx: &usize| std::cmp::Reverse(self.rules[idx].benefit());
+        for indices in self.by_root.values_mut() {
+            indices.sort_by_key(benefit);
+        }
+        self.any_root.sort_by_key(benefit);
+
         self
     }
+
+    /// The rules that may match an op named `name`, in the order they're tried
+    fn candidates(&self, name: &str) -> impl Iterator<Item = &dyn RewriteRule<T>> {
+        let mut rooted = self.by_root.get(name).map_or(&[][..], Vec::as_slice);
+        let mut any = self.any_root.as_slice();
+
+        // merge the two lists, both already in the order rules are tried
+        let key = |idx: usize| (std::cmp::Reverse(self.rules[idx].benefit()), idx);
+
+        std::iter::from_fn(move || {
+            let idx = match (rooted.first(), any.first()) {
+                (Some(&r), Some(&a)) => {
+                    if key(r) < key(a) {
+                        r
+                    } else {
+                        a
+                    }
+                }
+                (Some(&idx), None) | (None, Some(&idx)) => idx,
+                (None, None) => return None,
+            };
+
+            if rooted.first() == Some(&idx) {
+                rooted = &rooted[1..];
+            } else {
+                any = &any[1..];
+            }
+
+            Some(self.rules[idx].as_ref())
+        })
+    }
 }
 
-impl<T> RewriteRule<T> for RewriteRuleSet<T> {
+impl<T> Default for RewriteRuleSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: RewriteTarget> RewriteRule<T> for RewriteRuleSet<T> {
     fn apply(&self, node: &mut T) {
-        for rule in &self.rules {
+        for rule in self.candidates(node.root_name()) {
             rule.apply(node);
+
+            if node.changed() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Node {
+        name: &'static str,
+        applied: Vec<&'static str>,
+    }
+
+    impl RewriteTarget for Node {
+        fn root_name(&self) -> &'static str {
+            self.name
+        }
+
+        fn changed(&self) -> bool {
+            self.applied.contains(&"change")
+        }
+    }
+
+    struct Rule {
+        tag: &'static str,
+        roots: Option<&'static [&'static str]>,
+        benefit: u16,
+    }
+
+    impl RewriteRule<Node> for Rule {
+        fn apply(&self, node: &mut Node) {
+            node.applied.push(self.tag);
+        }
+
+        fn roots(&self) -> Option<&'static [&'static str]> {
+            self.roots
+        }
+
+        fn benefit(&self) -> u16 {
+            self.benefit
         }
     }
+
+    fn rule(tag: &'static str, roots: Option<&'static [&'static str]>, benefit: u16) -> Rule {
+        Rule {
+            tag,
+            roots,
+            benefit,
+        }
+    }
+
+    fn apply(set: &RewriteRuleSet<Node>, name: &'static str) -> Vec<&'static str> {
+        let mut node = Node {
+            name,
+            applied: Vec::new(),
+        };
+        set.apply(&mut node);
+        node.applied
+    }
+
+    #[test]
+    fn only_try_rules_rooted_on_the_op() {
+        let set = RewriteRuleSet::new()
+            .add_rule(rule("a", Some(&["test.a"]), 1))
+            .add_rule(rule("ab", Some(&["test.a", "test.b"]), 1))
+            .add_rule(rule("any", None, 1));
+
+        assert_eq!(apply(&set, "test.a"), vec!["a", "ab", "any"]);
+        assert_eq!(apply(&set, "test.b"), vec!["ab", "any"]);
+        assert_eq!(apply(&set, "test.c"), vec!["any"]);
+    }
+
+    #[test]
+    fn try_higher_benefit_first() {
+        let set = RewriteRuleSet::new()
+            .add_rule(rule("low", Some(&["test.a"]), 1))
+            .add_rule(rule("any", None, 2))
+            .add_rule(rule("high", Some(&["test.a"]), 3))
+            .add_rule(rule("tie", Some(&["test.a"]), 2));
+
+        assert_eq!(apply(&set, "test.a"), vec!["high", "any", "tie", "low"]);
+    }
+
+    #[test]
+    fn stop_after_a_change() {
+        let set = RewriteRuleSet::new()
+            .add_rule(rule("first", None, 1))
+            .add_rule(rule("change", None, 1))
+            .add_rule(rule("never", None, 1));
+
+        assert_eq!(apply(&set, "test.a"), vec!["first", "change"]);
+    }
 }
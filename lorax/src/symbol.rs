@@ -0,0 +1,136 @@
+// Ops named by a symbol, and the tables that find them by name
+
+use std::collections::HashMap;
+
+use crate::attr::Attribute;
+use crate::link::LinkedList;
+use crate::{Block, Operation, Ptr};
+
+/// The attribute holding the name of an op that defines a symbol
+pub const SYM_NAME: &str = "sym_name";
+
+impl Operation {
+    /// The name of the symbol this op defines, if it defines one
+    pub fn sym_name(&self) -> Option<&str> {
+        match self.attributes.get(SYM_NAME) {
+            Some(Attribute::Str(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Names of the symbols this op refers to, in its attributes at any depth
+    pub fn symbol_refs(&self) -> Vec<&str> {
+        fn collect<'a>(attr: &'a Attribute, refs: &mut Vec<&'a str>) {
+            match attr {
+                Attribute::Symbol(name) => refs.push(name),
+                Attribute::Array(items) => items.iter().for_each(|item| collect(item, refs)),
+                Attribute::Dict(dict) => dict.values().for_each(|item| collect(item, refs)),
+                _ => (),
+            }
+        }
+
+        let mut refs = Vec::new();
+        self.attributes
+            .values()
+            .for_each(|attr| collect(attr, &mut refs));
+        refs
+    }
+}
+
+/// The symbols defined by the ops directly in a block, indexed by name.
+///
+/// The outermost block is always a symbol table, blocks nested in an op are
+/// one if the op has the `SymbolTable` trait.
+#[derive(Debug)]
+pub struct SymbolTable {
+    symbols: HashMap<String, Ptr>,
+}
+
+impl SymbolTable {
+    /// Index the symbols in `block`. If a name is defined more than once, the
+    /// first definition is the one found.
+    pub fn new(block: &Block) -> Self {
+        let mut symbols = HashMap::new();
+        let mut next = *block.head();
+
+        while let Some(ptr) = next {
+            let op = block.get(ptr);
+
+            if let Some(name) = op.sym_name() {
+                symbols.entry(name.to_owned()).or_insert(ptr);
+            }
+
+            next = op.ahead;
+        }
+
+        Self { symbols }
+    }
+
+    /// The op in the indexed block defining `name`
+    pub fn lookup(&self, name: &str) -> Option<Ptr> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.symbols.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+/// The op directly in `block` defining the symbol `name`
+pub fn lookup_symbol<'a>(block: &'a Block, name: &str) -> Option<&'a Operation> {
+    block.iter().find(|op| op.sym_name() == Some(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    const MODULE: &str = "\
+.bb0:
+    test.func {sym_name = \"two\"} {
+    .bb1:
+        %0 := test.const {value = 2}
+        test.ret %0
+    }
+    test.func {sym_name = \"main\"} {
+    .bb2:
+        %0 := test.call {callee = @two, table = {entry = [@main]}}
+        test.ret %0
+    }
+";
+
+    #[test]
+    fn find_symbols_by_name() {
+        let block = parse(MODULE).unwrap();
+        let table = SymbolTable::new(&block);
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.lookup("two"), *block.head());
+        assert_eq!(table.lookup("main"), *block.tail());
+        assert_eq!(table.lookup("three"), None);
+
+        assert_eq!(
+            lookup_symbol(&block, "main").unwrap().sym_name(),
+            Some("main")
+        );
+    }
+
+    #[test]
+    fn collect_symbol_refs() {
+        let block = parse(MODULE).unwrap();
+        let main = lookup_symbol(&block, "main").unwrap();
+        let call = main.blocks[0].iter().next().unwrap();
+
+        assert_eq!(call.symbol_refs(), vec!["two", "main"]);
+        assert!(main.symbol_refs().is_empty());
+    }
+}
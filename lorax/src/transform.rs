@@ -1,84 +1,275 @@
 use crate::{
-    Block, Operation, RewriteRule, RewriteRuleSet, Value, link::LinkedList, pool::Ptr, walk_blocks,
+    Block, InsertPoint, OpBuilder, Operation, RewriteRule, RewriteRuleSet, RewriteTarget, Value,
+    WalkResult, link::LinkedList, pool::Ptr, walk::walk_regions_lent,
 };
 
+/// A cursor over the ops of a block, in order, for rules to rewrite the op it's at.
+///
+/// Moving on goes to the op after the current one as the list is then: ops
+/// inserted after it are visited next, ops inserted before it aren't, and if
+/// it was erased the cursor resumes where it was.
+///
+/// The block is one of a region, whose other blocks can use its values, so
+/// uses are counted and replaced in all of them.
 pub struct RewritingCtx<'a> {
-    block: &'a mut Block,
+    region: &'a mut [Block],
+    // the block of the region the cursor is in
+    index: usize,
     op: Ptr,
+    done: bool,
+
+    // the current op was erased, its slot is freed when moving on to `resume`,
+    // the op that was after it
+    erased: bool,
+    resume: Option<Ptr>,
+
+    // whether the IR was changed since the last `visit`, and ops created since
+    changed: bool,
+    created: Vec<Ptr>,
 }
 
 impl<'a> RewritingCtx<'a> {
     pub fn new(block: &'a mut Block, op: Ptr) -> Self {
-        Self { block, op }
+        Self::in_region(std::slice::from_mut(block), 0, op)
     }
 
+    /// A context at `op` in the block at `index` of the region
+    pub fn in_region(region: &'a mut [Block], index: usize, op: Ptr) -> Self {
+        Self {
+            region,
+            index,
+            op,
+            done: false,
+
+            erased: false,
+            resume: None,
+
+            changed: false,
+            created: Vec::new(),
+        }
+    }
+
+    /// A context at the first op of the block, which is done if there's none
     pub fn from_start(block: &'a mut Block) -> Self {
-        Self::new(block, Ptr::new(0))
+        let mut ctx = Self::new(block, Ptr::new(0));
+        ctx.enter(0);
+        ctx
     }
 
-    /// Allocate an operation in the pool, filling in the op's result with a def
-    pub fn alloc_op(&mut self, op: Operation) -> &Operation {
-        let ptr = self.block.pool.alloc(op);
+    /// Move to the first op of the block at `index` of the region, being done
+    /// if there's none
+    fn enter(&mut self, index: usize) {
+        self.leave();
+        self.index = index;
 
-        if let Some(val) = &mut self.deref_mut(ptr).result {
-            if let None = val.def {
-                val.def = Some(ptr);
-            }
+        match *self.block().head() {
+            Some(head) => self.visit(head),
+            None => self.done = true,
         }
+    }
+
+    /// The block the cursor is in
+    pub fn block(&self) -> &Block {
+        &self.region[self.index]
+    }
+
+    fn block_mut(&mut self) -> &mut Block {
+        &mut self.region[self.index]
+    }
+
+    /// Allocate an operation in the pool, filling in the op's result with a def
+    pub fn alloc_op(&mut self, op: Operation) -> &Operation {
+        let ptr = self.block_mut().pool.alloc(op);
+        self.block_mut().fill_def(ptr);
+        self.block_mut().add_uses(ptr);
+        self.created(ptr);
 
         self.deref(ptr)
     }
 
+    /// Move to the op after the current one, or where it was if it was erased
     fn advance(&mut self) {
-        if self.op < self.block.pool.len().into() {
-            self.op.idx += 1;
+        let next = if self.erased {
+            self.resume
+        } else {
+            self.get().ahead
+        };
+
+        match next {
+            Some(next) => self.visit(next),
+            None => {
+                self.leave();
+                self.done = true;
+            }
         }
     }
 
     pub fn get(&self) -> &Operation {
-        self.block.pool.deref(self.op)
+        self.block().pool.deref(self.op)
     }
 
+    /// Mutable access to the current op, which counts as changing it
     pub fn get_mut(&mut self) -> &mut Operation {
-        self.block.pool.deref_mut(self.op)
+        self.changed = true;
+        self.region[self.index].pool.deref_mut(self.op)
     }
 
     pub fn deref(&self, ptr: Ptr) -> &Operation {
-        self.block.pool.deref(ptr)
+        self.block().pool.deref(ptr)
+    }
+
+    /// The op in this block that defines `val`, if there is one
+    pub fn def_of(&self, val: Value) -> Option<&Operation> {
+        let op = self.block().pool.get(val.def?)?;
+        op.results.contains(&val).then_some(op)
     }
 
     pub fn deref_mut(&mut self, ptr: Ptr) -> &mut Operation {
-        self.block.pool.deref_mut(ptr)
+        self.changed = true;
+        self.block_mut().pool.deref_mut(ptr)
+    }
+
+    /// Insert an operation at `point` in the block, filling in the op's results with a def.
+    /// Without a location of its own, it gets the current op's.
+    pub fn insert(&mut self, point: InsertPoint, op: Operation) -> Ptr {
+        let location = self.get().location.clone();
+
+        let mut builder = OpBuilder::new(self.block_mut(), point);
+        builder.set_location(location);
+        let ptr = builder.insert(op);
+
+        self.created(ptr);
+        ptr
     }
 
+    /// Insert an operation before the current one
     pub fn insert_behind(&mut self, op: Operation) -> Ptr {
-        self.block.insert_behind(self.op, op)
+        self.insert(InsertPoint::Before(self.op), op)
+    }
+
+    /// Insert an operation after the current one
+    pub fn insert_ahead(&mut self, op: Operation) -> Ptr {
+        self.insert(InsertPoint::After(self.op), op)
+    }
+
+    fn created(&mut self, ptr: Ptr) {
+        self.changed = true;
+        self.created.push(ptr);
     }
 
     pub fn operands<'b>(&'a self) -> &'b [Value]
     where
         'a: 'b,
     {
-        &self.get().operands.as_slice()
+        self.get().operands.as_slice()
     }
 
     pub fn name(&self) -> &'static str {
         self.get().name
     }
 
+    /// The current op's first result
     pub fn result(&self) -> Option<Value> {
-        self.get().result
+        self.get().results.first().copied()
+    }
+
+    pub fn results<'b>(&'a self) -> &'b [Value]
+    where
+        'a: 'b,
+    {
+        self.get().results.as_slice()
+    }
+
+    /// Swap the current operation for `new`, keeping its place in the block
+    pub fn replace(&mut self, mut new: Operation) {
+        self.region[self.index].remove_uses(self.op);
+
+        let old = self.get_mut();
+        new.behind = old.behind;
+        new.ahead = old.ahead;
+        if new.location.is_unknown() {
+            new.location = std::mem::take(&mut old.location);
+        }
+        *old = new;
+
+        self.region[self.index].fill_def(self.op);
+        self.region[self.index].add_uses(self.op);
+        self.changed = true;
+    }
+
+    /// Make every use of `old` in the region refer to `new` instead
+    pub fn replace_all_uses_with(&mut self, old: Value, new: Value) {
+        self.changed |= old != new;
+        Block::replace_all_uses_in(self.region, old, new);
+    }
+
+    /// The ops in the current block using `val`, once per use
+    pub fn users(&self, val: Value) -> impl Iterator<Item = Ptr> + '_ {
+        self.block().users(val)
     }
 
-    pub fn replace(&mut self, new: Operation) {
-        *(self.get_mut()) = new;
+    /// How many times `val` is used in the whole region
+    pub fn num_uses(&self, val: Value) -> usize {
+        Block::num_uses_in(self.region, val)
+    }
+
+    /// Whether `val` is used once in the whole region
+    pub fn has_one_use(&self, val: Value) -> bool {
+        self.num_uses(val) == 1
+    }
+
+    /// Remove the current op from the block.
+    ///
+    /// It stays readable until the context moves on. A rule set stops at the
+    /// rule that erased it, but rules applied by hand should check `is_erased`.
+    pub fn erase(&mut self) {
+        self.resume = self.get().ahead;
+        self.region[self.index].unlink(self.op);
+        self.region[self.index].remove_uses(self.op);
+        self.erased = true;
+        self.changed = true;
+    }
+
+    pub fn is_erased(&self) -> bool {
+        self.erased
     }
 
     pub fn done(&self) -> bool {
-        self.op.idx >= self.block.pool.len()
+        self.done
     }
 
-    pub fn release(self) {}
+    /// Take out the ops created since the context moved to the current op,
+    /// for a rule that fails after inserting some
+    pub(crate) fn discard_created(&mut self) {
+        for ptr in std::mem::take(&mut self.created).into_iter().rev() {
+            self.region[self.index].erase_op(ptr);
+        }
+
+        self.changed = false;
+    }
+
+    /// Move to the op at `ptr`
+    fn visit(&mut self, ptr: Ptr) {
+        self.leave();
+        self.op = ptr;
+        self.done = false;
+    }
+
+    /// Be done with the current op, freeing it if it was erased
+    fn leave(&mut self) {
+        if std::mem::take(&mut self.erased) {
+            self.region[self.index].pool.free(self.op);
+        }
+
+        self.changed = false;
+        self.created.clear();
+    }
+
+    /// Finish rewriting, giving the region back
+    pub fn release(mut self) -> &'a mut [Block] {
+        self.leave();
+        self.region
+    }
 }
 
 pub fn rewrite_ops<'a, 'b>(block: &'a mut Block, pass: RewriteRuleSet<RewritingCtx<'b>>)
@@ -86,14 +277,406 @@ where
     Block: 'a,
     'a: 'b,
 {
-    for bl in walk_blocks(block) {
-        let mut ctx = RewritingCtx::from_start(bl);
+    // outer blocks are rewritten first, so rules see the ops they nest before
+    // those are rewritten in turn
+    walk_regions_lent(std::slice::from_mut(block), &mut |region| {
+        let mut ctx = RewritingCtx::in_region(region, 0, Ptr::new(0));
+
+        for index in 0..ctx.region.len() {
+            ctx.enter(index);
 
-        while !ctx.done() {
-            pass.apply(&mut ctx);
-            ctx.advance();
+            while !ctx.done() {
+                pass.apply(&mut ctx);
+                ctx.advance();
+            }
         }
 
-        ctx.release();
+        (ctx.release(), WalkResult::Advance)
+    });
+}
+
+impl RewriteTarget for RewritingCtx<'_> {
+    fn root_name(&self) -> &'static str {
+        self.name()
+    }
+
+    /// Whether the IR was changed since the context moved to the current op
+    fn changed(&self) -> bool {
+        self.changed
+    }
+}
+
+/// Limits for `rewrite_greedily`
+#[derive(Debug, Clone, Copy)]
+pub struct GreedyConfig {
+    /// How many times to go over a block's worklist before giving up on reaching a fixpoint
+    pub max_iterations: usize,
+}
+
+impl Default for GreedyConfig {
+    fn default() -> Self {
+        Self { max_iterations: 10 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GreedyOutcome {
+    /// Some rule changed the IR
+    pub changed: bool,
+    /// Every block reached a point where no rule applies, within the iteration cap
+    pub converged: bool,
+}
+
+/// Apply the rules to every op, at any depth, until none of them changes anything.
+///
+/// Each iteration goes over a block's worklist in list order. An op a rule
+/// changes is queued again for the next one, along with the ops it created and
+/// the users of their results, so rules get to match each other's output.
+pub fn rewrite_greedily<'a, 'b>(
+    block: &'a mut Block,
+    pass: RewriteRuleSet<RewritingCtx<'b>>,
+    config: GreedyConfig,
+) -> GreedyOutcome
+where
+    Block: 'a,
+    'a: 'b,
+{
+    let mut outcome = GreedyOutcome {
+        changed: false,
+        converged: true,
+    };
+
+    walk_regions_lent(std::slice::from_mut(block), &mut |region| {
+        let mut ctx = RewritingCtx::in_region(region, 0, Ptr::new(0));
+
+        for index in 0..ctx.region.len() {
+            ctx.enter(index);
+            let mut worklist: Vec<_> = ctx.block().ptrs().collect();
+            let mut iterations = 0;
+
+            while !worklist.is_empty() && iterations < config.max_iterations {
+                // go in list order, dropping ops that were erased or never linked in
+                let position = ctx.block().list_positions();
+                worklist.retain(|&ptr| ctx.block().pool.get(ptr).is_some());
+                worklist.sort_by_key(|ptr| position[ptr.idx]);
+                worklist.dedup();
+
+                let mut next = Vec::new();
+
+                for ptr in std::mem::take(&mut worklist) {
+                    // an earlier rewrite this iteration may have erased it
+                    if position[ptr.idx].is_none() || ctx.block().pool.get(ptr).is_none() {
+                        continue;
+                    }
+
+                    ctx.visit(ptr);
+                    pass.apply(&mut ctx);
+
+                    if !ctx.changed() {
+                        continue;
+                    }
+                    outcome.changed = true;
+
+                    if !ctx.is_erased() {
+                        next.push(ptr);
+                    }
+                    next.extend_from_slice(&ctx.created);
+
+                    for &op in [ptr].iter().chain(&ctx.created) {
+                        let Some(op) = ctx.block().pool.get(op) else {
+                            continue;
+                        };
+
+                        for &result in &op.results {
+                            next.extend(ctx.block().users(result));
+                        }
+                    }
+                }
+
+                worklist = next;
+                iterations += 1;
+            }
+
+            outcome.converged &= worklist.is_empty();
+        }
+
+        (ctx.release(), WalkResult::Advance)
+    });
+
+    outcome
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Location, parse};
+
+    #[test]
+    fn replace_keeps_links_and_uses() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const
+    %1 := test.neg %0
+    test.ret %1
+",
+        )
+        .unwrap();
+
+        let c = block.iter().next().unwrap().get_result();
+        let neg = block.iter().nth(1).unwrap().get_result();
+
+        let mut ctx = RewritingCtx::new(&mut block, neg.def.unwrap());
+        let new = Operation {
+            operands: vec![c, c],
+            results: vec![neg],
+            ..Operation::new("test.not")
+        };
+        ctx.replace(new);
+
+        let names: Vec<_> = block.iter().map(|op| op.name).collect();
+        assert_eq!(names, vec!["test.const", "test.not", "test.ret"]);
+        assert_eq!(block.uses(c).len(), 2);
+        assert!(block.has_one_use(neg));
+    }
+
+    #[test]
+    fn rewrites_inherit_locations() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.const loc(\"a.c\":1:1)
+    %1 := test.neg %0 loc(\"a.c\":2:8 to 2:9)
+",
+        )
+        .unwrap();
+
+        let neg = block.tail().unwrap();
+        let mut ctx = RewritingCtx::new(&mut block, neg);
+
+        let elsewhere = Location::span("b.c", 5, 1, 1);
+        let inserted = ctx.insert_behind(Operation::new("test.inserted"));
+        let kept = ctx.insert_behind(Operation::new("test.kept").at(elsewhere.clone()));
+        ctx.replace(Operation::new("test.replaced"));
+
+        let neg_location = Location::span("a.c", 2, 8, 1);
+        assert_eq!(block.get(inserted).location, neg_location);
+        assert_eq!(block.get(kept).location, elsewhere);
+        assert_eq!(block.get(neg).location, neg_location);
+    }
+
+    #[test]
+    fn insert_around_the_head() {
+        let mut block = parse(".bb0:\ntest.head\ntest.tail").unwrap();
+        let head = block.head().unwrap();
+        let mut ctx = RewritingCtx::new(&mut block, head);
+
+        ctx.insert_behind(Operation::new("test.first"));
+        ctx.insert_ahead(Operation::new("test.after_head"));
+
+        let names: Vec<_> = block.iter().map(|op| op.name).collect();
+        assert_eq!(
+            names,
+            ["test.first", "test.head", "test.after_head", "test.tail"]
+        );
+    }
+
+    struct EraseNeg;
+    impl<'a> RewriteRule<RewritingCtx<'a>> for EraseNeg {
+        fn apply(&self, ctx: &mut RewritingCtx<'a>) {
+            if !ctx.is_erased() && ctx.name() == "test.neg" {
+                ctx.erase();
+            }
+        }
+    }
+
+    #[test]
+    fn erase_during_rewrite() {
+        let mut block = parse(
+            "\
+.bb0:
+    func.func {
+    .bb1:
+        %0 := test.const
+        %1 := test.neg %0
+        %2 := test.neg %0
+        test.ret %0
+    }
+",
+        )
+        .unwrap();
+
+        rewrite_ops(
+            &mut block,
+            RewriteRuleSet::new().add_rule(EraseNeg).add_rule(EraseNeg),
+        );
+
+        let body = block
+            .walk_ops()
+            .next()
+            .unwrap()
+            .walk_blocks()
+            .next()
+            .unwrap();
+        let names: Vec<_> = body.walk_ops().map(|op| op.name).collect();
+        assert_eq!(names, vec!["test.const", "test.ret"]);
+        assert_eq!(body.len(), 2);
+        assert!(body.has_one_use(body.walk_ops().next().unwrap().get_result()));
+    }
+
+    #[test]
+    fn rewrite_reaches_nested_regions() {
+        let mut block = parse(
+            "\
+.bb0:
+    %0 := test.neg %0
+    func.func {
+    .bb1:
+        func.func {
+        .bb2:
+            %1 := test.neg %1
+        }
+    }
+",
+        )
+        .unwrap();
+
+        rewrite_ops(&mut block, RewriteRuleSet::new().add_rule(EraseNeg));
+
+        let mut names = Vec::new();
+        crate::walk_ops(&block, crate::WalkOrder::PreOrder, |op| {
+            names.push(op.name);
+            WalkResult::Advance
+        });
+        assert_eq!(names, vec!["func.func", "func.func"]);
+    }
+
+    /// Records the ops it visits, putting ops around `test.a` and erasing `test.b`
+    struct Record(std::rc::Rc<std::cell::RefCell<Vec<&'static str>>>);
+    impl<'a> RewriteRule<RewritingCtx<'a>> for Record {
+        fn apply(&self, ctx: &mut RewritingCtx<'a>) {
+            self.0.borrow_mut().push(ctx.name());
+
+            match ctx.name() {
+                "test.a" => {
+                    ctx.insert_behind(Operation::new("test.behind"));
+                    ctx.insert_ahead(Operation::new("test.ahead"));
+                }
+                "test.b" => ctx.erase(),
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn rewrite_in_list_order() {
+        let mut block = parse(".bb0:\n    test.a\n    test.b\n    test.c\n    test.d").unwrap();
+
+        // put the last op first, so list and pool order disagree
+        let d = block.tail().unwrap();
+        crate::OpBuilder::at_start(&mut block).move_op(d);
+
+        let visited = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        rewrite_ops(
+            &mut block,
+            RewriteRuleSet::new().add_rule(Record(visited.clone())),
+        );
+
+        assert_eq!(
+            *visited.borrow(),
+            ["test.d", "test.a", "test.ahead", "test.b", "test.c"]
+        );
+
+        let names: Vec<_> = block.walk_ops().map(|op| op.name).collect();
+        assert_eq!(
+            names,
+            ["test.d", "test.behind", "test.a", "test.ahead", "test.c"]
+        );
+    }
+
+    struct Rename(&'static str, &'static str);
+    impl<'a> RewriteRule<RewritingCtx<'a>> for Rename {
+        fn apply(&self, ctx: &mut RewritingCtx<'a>) {
+            if !ctx.is_erased() && ctx.name() == self.0 {
+                ctx.get_mut().name = self.1;
+            }
+        }
+    }
+
+    /// Turns `test.a` into `test.c`, with a `test.b` in front of it
+    struct Split;
+    impl<'a> RewriteRule<RewritingCtx<'a>> for Split {
+        fn apply(&self, ctx: &mut RewritingCtx<'a>) {
+            if ctx.name() == "test.a" {
+                ctx.insert_behind(Operation::new("test.b"));
+                ctx.get_mut().name = "test.c";
+            }
+        }
+    }
+
+    fn names(block: &Block) -> Vec<&'static str> {
+        block.iter().map(|op| op.name).collect()
+    }
+
+    #[test]
+    fn greedy_rewrites_to_fixpoint() {
+        let mut block = parse(".bb0:\n    test.x\n    test.a\n").unwrap();
+
+        // each rule only matches the output of the one after it
+        let rules = RewriteRuleSet::new()
+            .add_rule(Rename("test.d", "test.e"))
+            .add_rule(Rename("test.c", "test.d"))
+            .add_rule(Rename("test.b", "test.done"))
+            .add_rule(Split);
+
+        let outcome = rewrite_greedily(&mut block, rules, GreedyConfig::default());
+
+        assert_eq!(
+            outcome,
+            GreedyOutcome {
+                changed: true,
+                converged: true
+            }
+        );
+        assert_eq!(names(&block), vec!["test.x", "test.done", "test.e"]);
+    }
+
+    #[test]
+    fn greedy_reports_no_change() {
+        let mut block = parse(".bb0:\n    test.x\n").unwrap();
+
+        let outcome = rewrite_greedily(
+            &mut block,
+            RewriteRuleSet::new().add_rule(Rename("test.a", "test.b")),
+            GreedyConfig::default(),
+        );
+
+        assert_eq!(
+            outcome,
+            GreedyOutcome {
+                changed: false,
+                converged: true
+            }
+        );
+    }
+
+    #[test]
+    fn greedy_stops_at_iteration_cap() {
+        let mut block = parse(".bb0:\n    test.a\n").unwrap();
+
+        let rules = RewriteRuleSet::new()
+            .add_rule(Rename("test.a", "test.b"))
+            .add_rule(Rename("test.b", "test.a"));
+        let config = GreedyConfig { max_iterations: 3 };
+
+        let outcome = rewrite_greedily(&mut block, rules, config);
+
+        assert_eq!(
+            outcome,
+            GreedyOutcome {
+                changed: true,
+                converged: false
+            }
+        );
     }
 }
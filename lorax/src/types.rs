@@ -0,0 +1,354 @@
+// Types of values
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::parse::intern;
+
+/// What a type is made of. Types are interned, so this is only seen through a `Type`.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum TypeKind {
+    Int {
+        width: u32,
+        signed: bool,
+    },
+    Ptr(Type),
+    Func {
+        params: Vec<Type>,
+        results: Vec<Type>,
+    },
+    /// A type a dialect defines for its own use, printed as `!dialect.name`
+    Opaque {
+        dialect: &'static str,
+        name: &'static str,
+    },
+}
+
+/// A handle to an interned type, cheap to copy and compare.
+///
+/// Integer types are 1 to `Type::MAX_INT_WIDTH` bits wide, so that every
+/// value of one can be held by an integer attribute.
+#[derive(Clone, Copy)]
+pub struct Type(&'static TypeKind);
+
+impl Type {
+    /// The one `Type` for `kind`, leaked the first time it's seen
+    pub fn get(kind: TypeKind) -> Self {
+        static TYPES: Mutex<Option<HashSet<&'static TypeKind>>> = Mutex::new(None);
+
+        let mut types = TYPES.lock().expect("type interner was poisoned");
+        let types = types.get_or_insert_with(HashSet::new);
+
+        if let Some(&kind) = types.get(&kind) {
+            return Type(kind);
+        }
+
+        let kind: &'static TypeKind = Box::leak(Box::new(kind));
+        types.insert(kind);
+        Type(kind)
+    }
+
+    /// The widest an integer type can be
+    pub const MAX_INT_WIDTH: u32 = 64;
+
+    pub fn int(width: u32, signed: bool) -> Self {
+        assert!(
+            (1..=Self::MAX_INT_WIDTH).contains(&width),
+            "integer types are 1 to {} bits wide, not {width}",
+            Self::MAX_INT_WIDTH
+        );
+        Self::get(TypeKind::Int { width, signed })
+    }
+
+    pub fn ptr(pointee: Type) -> Self {
+        Self::get(TypeKind::Ptr(pointee))
+    }
+
+    pub fn func(params: Vec<Type>, results: Vec<Type>) -> Self {
+        Self::get(TypeKind::Func { params, results })
+    }
+
+    pub fn opaque(dialect: &'static str, name: &'static str) -> Self {
+        Self::get(TypeKind::Opaque { dialect, name })
+    }
+
+    pub fn kind(&self) -> &'static TypeKind {
+        self.0
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(self.0, TypeKind::Int { .. })
+    }
+}
+
+// interned, so the same kind is always at the same address
+impl PartialEq for Type {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Type {}
+
+impl Hash for Type {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0, state);
+    }
+}
+
+impl std::fmt::Debug for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Type({})", self)
+    }
+}
+
+fn fmt_types(types: &[Type], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for (i, ty) in types.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", ty)?;
+    }
+
+    Ok(())
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            TypeKind::Int {
+                width,
+                signed: true,
+            } => write!(f, "i{}", width),
+            TypeKind::Int {
+                width,
+                signed: false,
+            } => write!(f, "u{}", width),
+            TypeKind::Ptr(pointee) => write!(f, "ptr<{}>", pointee),
+            TypeKind::Func { params, results } => {
+                write!(f, "fn(")?;
+                fmt_types(params, f)?;
+                write!(f, ") -> (")?;
+                fmt_types(results, f)?;
+                write!(f, ")")
+            }
+            TypeKind::Opaque { dialect, name } => write!(f, "!{}.{}", dialect, name),
+        }
+    }
+}
+
+/// Parse a type from the start of `src`, returning it and the rest of `src`
+pub(crate) fn parse_prefix(src: &str) -> Option<(Type, &str)> {
+    let word_end = |src: &str| {
+        src.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(src.len())
+    };
+
+    if let Some(rest) = src.strip_prefix('!') {
+        let (dialect, rest) = rest.split_at(word_end(rest));
+        let rest = rest.strip_prefix('.')?;
+        let (name, rest) = rest.split_at(word_end(rest));
+
+        if dialect.is_empty() || name.is_empty() {
+            return None;
+        }
+
+        return Some((Type::opaque(intern(dialect), intern(name)), rest));
+    }
+
+    if let Some(rest) = src.strip_prefix("ptr<") {
+        let (pointee, rest) = parse_prefix(rest)?;
+        return Some((Type::ptr(pointee), rest.strip_prefix('>')?));
+    }
+
+    if let Some(rest) = src.strip_prefix("fn") {
+        let (params, rest) = parse_list(rest)?;
+        let rest = rest.trim_start().strip_prefix("->")?.trim_start();
+        let (results, rest) = parse_list(rest)?;
+        return Some((Type::func(params, results), rest));
+    }
+
+    let (word, rest) = src.split_at(word_end(src));
+    let signed = match word.get(..1)? {
+        "i" => true,
+        "u" => false,
+        _ => return None,
+    };
+    let width = word[1..].parse().ok()?;
+    if !(1..=Type::MAX_INT_WIDTH).contains(&width) {
+        return None;
+    }
+
+    Some((Type::int(width, signed), rest))
+}
+
+/// Parse a parenthesized list of types, like `(i32, u8)`
+fn parse_list(src: &str) -> Option<(Vec<Type>, &str)> {
+    let mut rest = src.strip_prefix('(')?;
+    let mut types = Vec::new();
+
+    if let Some(rest) = rest.strip_prefix(')') {
+        return Some((types, rest));
+    }
+
+    loop {
+        let (ty, after) = parse_prefix(rest.trim_start())?;
+        types.push(ty);
+
+        match after.strip_prefix(',') {
+            Some(after) => rest = after,
+            None => return Some((types, after.strip_prefix(')')?)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseTypeError;
+
+impl Display for ParseTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid type")
+    }
+}
+
+impl std::error::Error for ParseTypeError {}
+
+impl FromStr for Type {
+    type Err = ParseTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_prefix(s) {
+            Some((ty, "")) => Ok(ty),
+            _ => Err(ParseTypeError),
+        }
+    }
+}
+
+/// What an op accepts as the type of one of its operands or its result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeConstraint {
+    Any,
+    Int,
+    Ptr,
+    Func,
+    /// Exactly the type printed like this, e.g. `"i32"`
+    Exact(&'static str),
+    /// The same type as the named operand or result, or as the value of the
+    /// named integer attribute
+    SameAs(&'static str),
+}
+
+impl TypeConstraint {
+    /// Whether `ty` is accepted, given the type of whatever `SameAs` refers to
+    pub fn accepts(&self, ty: Option<Type>, same_as: impl FnOnce(&str) -> Option<Type>) -> bool {
+        match (self, ty) {
+            (TypeConstraint::Any, _) => true,
+            (_, None) => false,
+
+            (TypeConstraint::Int, Some(ty)) => ty.is_int(),
+            (TypeConstraint::Ptr, Some(ty)) => matches!(ty.kind(), TypeKind::Ptr(_)),
+            (TypeConstraint::Func, Some(ty)) => matches!(ty.kind(), TypeKind::Func { .. }),
+            (TypeConstraint::Exact(name), Some(ty)) => ty.to_string() == *name,
+            (TypeConstraint::SameAs(operand), ty) => same_as(operand) == ty,
+        }
+    }
+}
+
+impl Display for TypeConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeConstraint::Any => write!(f, "any type"),
+            TypeConstraint::Int => write!(f, "an integer"),
+            TypeConstraint::Ptr => write!(f, "a pointer"),
+            TypeConstraint::Func => write!(f, "a function"),
+            TypeConstraint::Exact(name) => write!(f, "{}", name),
+            TypeConstraint::SameAs(operand) => write!(f, "the type of '{}'", operand),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn types_are_interned() {
+        let i32 = Type::int(32, true);
+
+        assert_eq!(i32, Type::int(32, true));
+        assert_ne!(i32, Type::int(32, false));
+        assert_ne!(i32, Type::int(64, true));
+        assert_eq!(Type::ptr(i32), Type::ptr(Type::int(32, true)));
+        assert!(std::ptr::eq(i32.kind(), Type::int(32, true).kind()));
+    }
+
+    #[test]
+    #[should_panic(expected = "integer types are 1 to 64 bits wide")]
+    fn reject_wide_int_types() {
+        Type::int(128, false);
+    }
+
+    #[test]
+    fn print_types() {
+        let i32 = Type::int(32, true);
+        let u8 = Type::int(8, false);
+
+        assert_eq!(i32.to_string(), "i32");
+        assert_eq!(u8.to_string(), "u8");
+        assert_eq!(Type::ptr(Type::ptr(u8)).to_string(), "ptr<ptr<u8>>");
+        assert_eq!(
+            Type::func(vec![i32, u8], vec![i32]).to_string(),
+            "fn(i32, u8) -> (i32)"
+        );
+        assert_eq!(Type::opaque("x86", "flags").to_string(), "!x86.flags");
+    }
+
+    #[test]
+    fn parse_printed_types() {
+        let types = [
+            Type::int(32, true),
+            Type::int(1, false),
+            Type::ptr(Type::ptr(Type::int(8, false))),
+            Type::func(Vec::new(), Vec::new()),
+            Type::func(
+                vec![Type::int(64, true), Type::ptr(Type::int(8, true))],
+                vec![Type::int(32, true)],
+            ),
+            Type::opaque("x86", "flags"),
+        ];
+
+        for ty in types {
+            assert_eq!(ty.to_string().parse(), Ok(ty));
+        }
+
+        assert_eq!("i32 ".parse::<Type>(), Err(ParseTypeError));
+        assert_eq!("ptr<i32".parse::<Type>(), Err(ParseTypeError));
+        assert_eq!("f32".parse::<Type>(), Err(ParseTypeError));
+        assert_eq!("i".parse::<Type>(), Err(ParseTypeError));
+        assert_eq!("i0".parse::<Type>(), Err(ParseTypeError));
+        assert_eq!("u128".parse::<Type>(), Err(ParseTypeError));
+        assert_eq!("i64".parse(), Ok(Type::int(Type::MAX_INT_WIDTH, true)));
+    }
+
+    #[test]
+    fn check_constraints() {
+        let i32 = Some(Type::int(32, true));
+        let ptr = i32.map(Type::ptr);
+        let none = |_: &str| None;
+
+        assert!(TypeConstraint::Int.accepts(i32, none));
+        assert!(!TypeConstraint::Int.accepts(ptr, none));
+        assert!(TypeConstraint::Ptr.accepts(ptr, none));
+        assert!(TypeConstraint::Exact("i32").accepts(i32, none));
+        assert!(!TypeConstraint::Exact("i64").accepts(i32, none));
+        assert!(TypeConstraint::SameAs("val").accepts(i32, |_| i32));
+        assert!(!TypeConstraint::SameAs("val").accepts(i32, |_| ptr));
+
+        // untyped values only satisfy `Any`
+        assert!(TypeConstraint::Any.accepts(None, none));
+        assert!(!TypeConstraint::Int.accepts(None, none));
+    }
+}
@@ -0,0 +1,1247 @@
+// Check that IR is well formed
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use crate::analysis::DominatorTree;
+use crate::attr::{AttrKind, Attribute};
+use crate::dialect::{Dialect, OpDefinition, OpTrait};
+use crate::link::LinkedList;
+use crate::types::{Type, TypeConstraint};
+use crate::{Block, Operation, Ptr, Value};
+
+/// What a dialect expects of one of its operations.
+#[derive(Debug, Clone, Default)]
+pub struct OpConstraint {
+    operands: usize,
+    operand_names: Vec<&'static str>,
+    results: usize,
+    result_names: Vec<&'static str>,
+    blocks: usize,
+    successors: usize,
+    // and the kind of value each one holds, if that's constrained
+    attributes: Vec<(&'static str, Option<AttrKind>)>,
+    types: Vec<(&'static str, TypeConstraint)>,
+    terminator: bool,
+    isolated: bool,
+    symbol_table: bool,
+}
+
+impl OpConstraint {
+    pub fn new(operands: usize) -> Self {
+        Self {
+            operands,
+            ..Self::default()
+        }
+    }
+
+    /// Name the operands, in order, so their types can be constrained
+    pub fn operand_names(mut self, names: &[&'static str]) -> Self {
+        self.operand_names = names.to_vec();
+        self
+    }
+
+    pub fn result(self) -> Self {
+        self.results(1)
+    }
+
+    pub fn results(mut self, count: usize) -> Self {
+        self.results = count;
+        self
+    }
+
+    /// Name the results, in order, so their types can be constrained.
+    /// A single result is called `"result"` unless it's named otherwise.
+    pub fn result_names(mut self, names: &[&'static str]) -> Self {
+        self.result_names = names.to_vec();
+        self
+    }
+
+    /// Constrain the type of the operand or result named `name`
+    pub fn ty(mut self, name: &'static str, constraint: TypeConstraint) -> Self {
+        self.types.push((name, constraint));
+        self
+    }
+
+    pub fn blocks(mut self, count: usize) -> Self {
+        self.blocks = count;
+        self
+    }
+
+    /// The op branches to `count` blocks
+    pub fn successors(mut self, count: usize) -> Self {
+        self.successors = count;
+        self
+    }
+
+    pub fn attr(mut self, name: &'static str) -> Self {
+        self.attributes.push((name, None));
+        self
+    }
+
+    /// The op must have the attribute, holding a value of `kind`
+    pub fn attr_of(mut self, name: &'static str, kind: AttrKind) -> Self {
+        self.attributes.push((name, Some(kind)));
+        self
+    }
+
+    /// The op must be the last one in its block
+    pub fn terminator(mut self) -> Self {
+        self.terminator = true;
+        self
+    }
+
+    /// Blocks nested in the op can't use values from outside it
+    pub fn isolated(mut self) -> Self {
+        self.isolated = true;
+        self
+    }
+
+    /// The ops in the op's blocks are symbols, which references nested in it can name
+    pub fn symbol_table(mut self) -> Self {
+        self.symbol_table = true;
+        self
+    }
+}
+
+impl From<&OpDefinition> for OpConstraint {
+    fn from(def: &OpDefinition) -> Self {
+        Self {
+            operands: def.operands,
+            operand_names: def.operand_names.to_vec(),
+            results: def.results,
+            result_names: def.result_names.to_vec(),
+            blocks: def.blocks,
+            successors: def.successors,
+            attributes: def
+                .attributes
+                .iter()
+                .map(|&(name, kind)| (name, Some(kind)))
+                .collect(),
+            types: def.types.to_vec(),
+            terminator: def.has_trait(OpTrait::Terminator),
+            isolated: def.has_trait(OpTrait::IsolatedFromAbove),
+            symbol_table: def.has_trait(OpTrait::SymbolTable),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiagnosticKind {
+    /// The value isn't defined before this use, in this block or an enclosing one
+    UndefinedValue(Value),
+    /// The value doesn't point back to the op that defines it
+    MissingDef(Value),
+    /// The value's def is out of range, or points at an op that doesn't produce it
+    BadDef(Value),
+    /// The value was made under another context than the block holding it
+    ForeignValue(Value),
+    /// The block was made under another context than the block holding its op
+    ForeignBlock(usize),
+    MisplacedTerminator,
+    /// The op is named like one of a dialect the verifier knows, which has no such op
+    UnknownOp,
+    OperandCount {
+        expected: usize,
+        found: usize,
+    },
+    ResultCount {
+        expected: usize,
+        found: usize,
+    },
+    BlockCount {
+        expected: usize,
+        found: usize,
+    },
+    SuccessorCount {
+        expected: usize,
+        found: usize,
+    },
+    /// The successor isn't a block in the same region as the op's block
+    UnknownSuccessor(usize),
+    /// The values passed to a successor don't match its arguments
+    SuccessorArgs {
+        block: usize,
+        expected: Vec<Option<Type>>,
+        found: Vec<Option<Type>>,
+    },
+    MissingAttribute(&'static str),
+    /// Another op in the same symbol table already defines the name
+    DuplicateSymbol(String),
+    /// No enclosing symbol table defines the name
+    UndefinedSymbol(String),
+    AttributeKind {
+        name: &'static str,
+        expected: AttrKind,
+        found: AttrKind,
+    },
+    /// The named operand or result doesn't have a type the op accepts
+    TypeMismatch {
+        name: &'static str,
+        expected: TypeConstraint,
+        found: Option<Type>,
+    },
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticKind::UndefinedValue(val) => write!(f, "{} is used before it's defined", val),
+            DiagnosticKind::MissingDef(val) => write!(f, "{} has no def", val),
+            DiagnosticKind::BadDef(val) => write!(f, "{} has a def that doesn't define it", val),
+            DiagnosticKind::ForeignValue(val) => {
+                write!(f, "{} was made under another context", val)
+            }
+            DiagnosticKind::ForeignBlock(block) => {
+                write!(f, ".bb{} was made under another context", block)
+            }
+            DiagnosticKind::MisplacedTerminator => {
+                write!(f, "terminator isn't at the end of its block")
+            }
+            DiagnosticKind::UnknownOp => write!(f, "no such op in its dialect"),
+            DiagnosticKind::OperandCount { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            DiagnosticKind::ResultCount { expected, found } => {
+                write!(f, "expected {} results, found {}", expected, found)
+            }
+            DiagnosticKind::BlockCount { expected, found } => {
+                write!(f, "expected {} blocks, found {}", expected, found)
+            }
+            DiagnosticKind::SuccessorCount { expected, found } => {
+                write!(f, "expected {} successors, found {}", expected, found)
+            }
+            DiagnosticKind::UnknownSuccessor(block) => {
+                write!(f, ".bb{} isn't a block in this region", block)
+            }
+            DiagnosticKind::SuccessorArgs {
+                block,
+                expected,
+                found,
+            } => write!(
+                f,
+                "passed ({}) to .bb{}, which takes ({})",
+                fmt_types(found),
+                block,
+                fmt_types(expected)
+            ),
+            DiagnosticKind::MissingAttribute(name) => write!(f, "missing attribute '{}'", name),
+            DiagnosticKind::DuplicateSymbol(name) => {
+                write!(f, "symbol @{} is already defined", name)
+            }
+            DiagnosticKind::UndefinedSymbol(name) => write!(f, "symbol @{} isn't defined", name),
+            DiagnosticKind::AttributeKind {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "attribute '{}' should be {}, found {}",
+                name, expected, found
+            ),
+            DiagnosticKind::TypeMismatch {
+                name,
+                expected,
+                found: Some(found),
+            } => write!(f, "'{}' should be {}, found {}", name, expected, found),
+            DiagnosticKind::TypeMismatch {
+                name,
+                expected,
+                found: None,
+            } => write!(f, "'{}' should be {}, but it's untyped", name, expected),
+        }
+    }
+}
+
+fn fmt_types(types: &[Option<Type>]) -> String {
+    let types: Vec<_> = types
+        .iter()
+        .map(|ty| ty.map_or("untyped".to_owned(), |ty| ty.to_string()))
+        .collect();
+    types.join(", ")
+}
+
+/// A problem found by the verifier, and the op it was found on.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub block: usize,
+    pub op: Ptr,
+    pub name: &'static str,
+    pub kind: DiagnosticKind,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' in .bb{}: {}", self.name, self.block, self.kind)
+    }
+}
+
+/// Values defined so far in a block, and the op defining them, or none for
+/// the block's arguments. A block also sees the scopes of the blocks
+/// dominating it and of the blocks it's nested in.
+type Scope = HashMap<Value, Option<Ptr>>;
+
+/// Names of the symbols in a symbol table
+type Symbols = HashSet<String>;
+
+/// Checks IR against the structural rules every op follows,
+/// and against the constraints dialects register for their own ops.
+///
+/// Ops without a registered constraint only get the structural checks,
+/// unless they're named like an op of a dialect the verifier was given,
+/// which knows all of its ops.
+pub struct Verifier {
+    constraints: HashMap<&'static str, OpConstraint>,
+    dialects: HashSet<&'static str>,
+}
+
+impl Verifier {
+    pub fn new() -> Self {
+        Self {
+            constraints: HashMap::new(),
+            dialects: HashSet::new(),
+        }
+    }
+
+    pub fn constrain(mut self, name: &'static str, constraint: OpConstraint) -> Self {
+        self.constraints.insert(name, constraint);
+        self
+    }
+
+    /// Constrain every op of the dialect by its definition
+    pub fn dialect(mut self, dialect: &Dialect) -> Self {
+        self.dialects.insert(dialect.name());
+
+        dialect.ops().iter().fold(self, |verifier, def| {
+            verifier.constrain(def.name, OpConstraint::from(*def))
+        })
+    }
+
+    pub fn verify(&self, block: &Block) -> Result<(), Vec<Diagnostic>> {
+        let mut diagnostics = Vec::new();
+
+        // the outermost block is a symbol table, whatever it's nested in
+        self.verify_block(
+            block,
+            std::slice::from_ref(block),
+            &mut Vec::new(),
+            &mut Vec::new(),
+            true,
+            &mut diagnostics,
+        );
+
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    /// Verify the blocks of a region, each seeing the values of the blocks
+    /// dominating it. Blocks no branch reaches only see the enclosing scopes.
+    fn verify_region(
+        &self,
+        region: &[Block],
+        scopes: &mut Vec<Scope>,
+        symbols: &mut Vec<Symbols>,
+        symbol_table: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let dominators = DominatorTree::new(region);
+        let index: HashMap<usize, &Block> = region.iter().map(|block| (block.id, block)).collect();
+        let mut order = dominators.preorder();
+        order.extend(
+            region
+                .iter()
+                .map(Block::id)
+                .filter(|&id| !dominators.contains(id)),
+        );
+
+        // the scopes of the blocks dominating the current one, innermost last
+        let mut dominating: Vec<usize> = Vec::new();
+
+        for id in order {
+            while let Some(&last) = dominating.last()
+                && !dominators.dominates(last, id)
+            {
+                dominating.pop();
+                scopes.pop();
+            }
+
+            let block = index[&id];
+
+            self.verify_block(block, region, scopes, symbols, symbol_table, diagnostics);
+            dominating.push(id);
+        }
+
+        for _ in dominating {
+            scopes.pop();
+        }
+    }
+
+    /// Verify the block, leaving a scope with its values for the caller to pop
+    fn verify_block(
+        &self,
+        block: &Block,
+        region: &[Block],
+        scopes: &mut Vec<Scope>,
+        symbols: &mut Vec<Symbols>,
+        symbol_table: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        scopes.push(block.args().iter().map(|&arg| (arg, None)).collect());
+
+        // symbols can be referenced before they're defined, so find them all up front
+        if symbol_table {
+            let mut names = Symbols::new();
+            let mut next = *block.head();
+
+            while let Some(ptr) = next {
+                let op = block.get(ptr);
+
+                if let Some(name) = op.sym_name()
+                    && !names.insert(name.to_owned())
+                {
+                    diagnostics.push(Diagnostic {
+                        block: block.id,
+                        op: ptr,
+                        name: op.name,
+                        kind: DiagnosticKind::DuplicateSymbol(name.to_owned()),
+                    });
+                }
+
+                next = op.ahead;
+            }
+
+            symbols.push(names);
+        }
+
+        let mut next = *block.head();
+
+        while let Some(ptr) = next {
+            let op = block.get(ptr);
+            let mut report = |kind| {
+                diagnostics.push(Diagnostic {
+                    block: block.id,
+                    op: ptr,
+                    name: op.name,
+                    kind,
+                })
+            };
+
+            // ids only identify values and blocks within one context
+            for &val in op.all_operands().chain(&op.results) {
+                if val.context() != block.context() {
+                    report(DiagnosticKind::ForeignValue(val));
+                }
+            }
+
+            for nested in op.walk_blocks() {
+                if nested.context() != block.context() {
+                    report(DiagnosticKind::ForeignBlock(nested.id));
+                }
+            }
+
+            for &operand in op.all_operands() {
+                // an operand that's also the result is written in place (e.g. `x86.mov`)
+                if op.results.contains(&operand) {
+                    continue;
+                }
+
+                match scopes.iter().rev().find_map(|scope| scope.get(&operand)) {
+                    None => report(DiagnosticKind::UndefinedValue(operand)),
+                    Some(&def) if operand.def != def => match operand.def {
+                        None => report(DiagnosticKind::MissingDef(operand)),
+                        Some(_) => report(DiagnosticKind::BadDef(operand)),
+                    },
+                    _ => (),
+                }
+            }
+
+            for &result in &op.results {
+                match result.def {
+                    None => report(DiagnosticKind::MissingDef(result)),
+                    Some(def) if !defines(block, def, result) => {
+                        report(DiagnosticKind::BadDef(result))
+                    }
+                    _ => (),
+                }
+
+                let scope = scopes.last_mut().expect("the current block has a scope");
+                scope.entry(result).or_insert(Some(ptr));
+            }
+
+            for successor in &op.successors {
+                let Some(target) = region.iter().find(|b| b.id == successor.block) else {
+                    report(DiagnosticKind::UnknownSuccessor(successor.block));
+                    continue;
+                };
+
+                let expected: Vec<_> = target.args().iter().map(Value::ty).collect();
+                let found: Vec<_> = successor.args.iter().map(Value::ty).collect();
+
+                if expected != found {
+                    report(DiagnosticKind::SuccessorArgs {
+                        block: successor.block,
+                        expected,
+                        found,
+                    });
+                }
+            }
+
+            for name in op.symbol_refs() {
+                if !symbols.iter().any(|table| table.contains(name)) {
+                    report(DiagnosticKind::UndefinedSymbol(name.to_owned()));
+                }
+            }
+
+            let constraint = self.constraints.get(op.name);
+
+            if let Some(constraint) = constraint {
+                check_constraint(op, constraint, &mut report);
+            } else if let Some((dialect, _)) = op.name.split_once('.')
+                && self.dialects.contains(dialect)
+            {
+                report(DiagnosticKind::UnknownOp);
+            }
+
+            let nested_table = constraint.is_some_and(|c| c.symbol_table);
+
+            // nothing from the enclosing scopes is visible inside an isolated op
+            let mut isolated = Vec::new();
+            let outer = if constraint.is_some_and(|c| c.isolated) {
+                &mut isolated
+            } else {
+                &mut *scopes
+            };
+
+            self.verify_region(&op.blocks, outer, symbols, nested_table, diagnostics);
+
+            next = op.ahead;
+        }
+
+        if symbol_table {
+            symbols.pop();
+        }
+    }
+}
+
+impl Default for Verifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn defines(block: &Block, def: Ptr, val: Value) -> bool {
+    block
+        .pool
+        .get(def)
+        .is_some_and(|op| op.results.contains(&val))
+}
+
+fn check_constraint(
+    op: &Operation,
+    constraint: &OpConstraint,
+    report: &mut impl FnMut(DiagnosticKind),
+) {
+    if op.operands.len() != constraint.operands {
+        report(DiagnosticKind::OperandCount {
+            expected: constraint.operands,
+            found: op.operands.len(),
+        });
+    }
+
+    if op.results.len() != constraint.results {
+        report(DiagnosticKind::ResultCount {
+            expected: constraint.results,
+            found: op.results.len(),
+        });
+    }
+
+    if op.blocks.len() != constraint.blocks {
+        report(DiagnosticKind::BlockCount {
+            expected: constraint.blocks,
+            found: op.blocks.len(),
+        });
+    }
+
+    if op.successors.len() != constraint.successors {
+        report(DiagnosticKind::SuccessorCount {
+            expected: constraint.successors,
+            found: op.successors.len(),
+        });
+    }
+
+    for &(name, kind) in &constraint.attributes {
+        match (op.attributes.get(name), kind) {
+            (None, _) => report(DiagnosticKind::MissingAttribute(name)),
+            (Some(attr), Some(expected)) if attr.kind() != expected => {
+                report(DiagnosticKind::AttributeKind {
+                    name,
+                    expected,
+                    found: attr.kind(),
+                })
+            }
+            _ => (),
+        }
+    }
+
+    if constraint.terminator && op.ahead.is_some() {
+        report(DiagnosticKind::MisplacedTerminator);
+    }
+
+    // operands or results that are missing were already reported above
+    let value = |name: &str| {
+        let find = |names: &[&str], values: &[Value]| {
+            let idx = names.iter().position(|&n| n == name)?;
+            values.get(idx).copied()
+        };
+
+        find(&constraint.operand_names, &op.operands)
+            .or_else(|| find(&constraint.result_names, &op.results))
+            .or_else(|| match name {
+                "result" => op.results.first().copied(),
+                _ => None,
+            })
+    };
+
+    for &(name, expected) in &constraint.types {
+        let Some(val) = value(name) else {
+            continue;
+        };
+
+        let same_as = |other: &str| match value(other) {
+            Some(val) => val.ty(),
+            None => op.attributes.get(other).and_then(Attribute::int_type),
+        };
+
+        if !expected.accepts(val.ty(), same_as) {
+            report(DiagnosticKind::TypeMismatch {
+                name,
+                expected,
+                found: val.ty(),
+            });
+        }
+    }
+}
+
+/// Verify only the structural rules, without any dialect constraints.
+pub fn verify(block: &Block) -> Result<(), Vec<Diagnostic>> {
+    Verifier::new().verify(block)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Context, parse};
+
+    fn verifier() -> Verifier {
+        Verifier::new()
+            .constrain(
+                "test.const",
+                OpConstraint::new(0)
+                    .result()
+                    .attr_of("value", AttrKind::Int),
+            )
+            .constrain("test.neg", OpConstraint::new(1).result())
+            .constrain("test.func", OpConstraint::new(0).blocks(1))
+            .constrain("test.ret", OpConstraint::new(1).terminator())
+    }
+
+    fn kinds(src: &str) -> Vec<DiagnosticKind> {
+        let block = parse(src).unwrap();
+
+        match verifier().verify(&block) {
+            Ok(()) => Vec::new(),
+            Err(diagnostics) => diagnostics.into_iter().map(|d| d.kind).collect(),
+        }
+    }
+
+    #[test]
+    fn accept_well_formed() {
+        let src = "\
+.bb0:
+    test.func {
+    .bb1:
+        %0 := test.const {value = 1}
+        %1 := test.neg %0
+        test.ret %1
+    }
+";
+        assert_eq!(kinds(src), vec![]);
+    }
+
+    #[test]
+    fn accept_uses_from_enclosing_block() {
+        let src = "\
+.bb0:
+    %0 := test.const {value = 1}
+    test.func {
+    .bb1:
+        test.ret %0
+    }
+";
+        assert_eq!(kinds(src), vec![]);
+    }
+
+    #[test]
+    fn reject_use_before_def() {
+        let src = "\
+.bb0:
+    %1 := test.neg %0
+    %0 := test.const {value = 1}
+";
+        assert!(matches!(
+            kinds(src).as_slice(),
+            [DiagnosticKind::UndefinedValue(_)]
+        ));
+    }
+
+    #[test]
+    fn reject_use_from_sibling_block() {
+        let src = "\
+.bb0:
+    test.func {
+    .bb1:
+        %0 := test.const {value = 1}
+        test.ret %0
+    }
+    test.func {
+    .bb2:
+        test.ret %0
+    }
+";
+        assert!(matches!(
+            kinds(src).as_slice(),
+            [DiagnosticKind::UndefinedValue(_)]
+        ));
+    }
+
+    #[test]
+    fn accept_branch_to_block_args() {
+        let src = "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const {value = 1}
+        test.br .bb2(%0)
+    .bb2(%1):
+        %2 := test.neg %1
+        test.br .bb1
+    }
+";
+        assert_eq!(kinds(src), vec![]);
+    }
+
+    #[test]
+    fn accept_uses_from_dominating_blocks() {
+        let src = "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const {value = 1}
+        test.br .bb2, .bb3
+    .bb2:
+        %1 := test.neg %0
+        test.br .bb4
+    .bb3:
+        test.br .bb4
+    .bb4:
+        test.ret %0
+    }
+";
+        assert_eq!(kinds(src), vec![]);
+    }
+
+    #[test]
+    fn accept_uses_from_dominating_blocks_printed_later() {
+        let src = "\
+.bb0:
+    test.region {
+    .bb1:
+        test.br .bb3
+    .bb2:
+        %1 := test.neg %0
+        test.loop {
+        .bb4:
+            test.use %0, %1
+        }
+        test.ret %0
+    .bb3:
+        %0 := test.const {value = 1}
+        test.br .bb2
+    }
+";
+        assert_eq!(kinds(src), vec![]);
+    }
+
+    #[test]
+    fn reject_uses_from_blocks_not_dominating() {
+        let src = "\
+.bb0:
+    test.region {
+    .bb1:
+        test.br .bb2, .bb3
+    .bb2:
+        %0 := test.const {value = 1}
+        test.br .bb3
+    .bb3:
+        test.ret %0
+    .bb4:
+        test.ret %0
+    }
+";
+        assert!(matches!(
+            kinds(src).as_slice(),
+            [
+                DiagnosticKind::UndefinedValue(_),
+                DiagnosticKind::UndefinedValue(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn reject_values_from_other_contexts() {
+        let src = ".bb0:\n    %0 := test.const\n    test.use %0 {\n    .bb1:\n    }\n";
+        let mut block = Context::new().enter(|| parse(src).unwrap());
+        let (val, nested) = Context::new().enter(|| (Value::new(None), Block::new()));
+        let def = block.get(Ptr::new(0)).get_result();
+
+        let user = block.get_mut(Ptr::new(1));
+        user.operands.push(val);
+        user.blocks.push(nested);
+
+        let kinds: Vec<_> = Verifier::new()
+            .verify(&block)
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+
+        // the foreign value shares an id with %0, but isn't the same value
+        assert_eq!(val.to_string(), def.to_string());
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                DiagnosticKind::ForeignValue(foreign),
+                DiagnosticKind::ForeignBlock(_),
+                DiagnosticKind::UndefinedValue(_),
+            ] if *foreign == val
+        ));
+    }
+
+    #[test]
+    fn reject_bad_successors() {
+        let src = "\
+.bb0:
+    test.region {
+    .bb1:
+        %0: i32 := test.const {value = 1}
+        test.br .bb2(%0, %0)
+    .bb2(%1: u8):
+        test.br .bb3
+    }
+    test.region {
+    .bb3:
+        test.br .bb2(%1)
+    }
+";
+        let i32 = Some(Type::int(32, true));
+        let u8 = Some(Type::int(8, false));
+        let kinds = kinds(src);
+
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                DiagnosticKind::SuccessorArgs { expected, found, .. },
+                DiagnosticKind::UnknownSuccessor(_),
+                DiagnosticKind::UndefinedValue(_),
+                DiagnosticKind::UnknownSuccessor(_),
+            ] if *expected == vec![u8] && *found == vec![i32, i32]
+        ));
+    }
+
+    #[test]
+    fn count_successors() {
+        let src = "\
+.bb0:
+    test.func {
+    .bb1:
+        test.br .bb1
+    }
+";
+        let block = parse(src).unwrap();
+        let verifier = Verifier::new().constrain("test.br", OpConstraint::new(0).successors(2));
+
+        assert!(matches!(
+            verifier.verify(&block).unwrap_err().as_slice(),
+            [Diagnostic {
+                kind: DiagnosticKind::SuccessorCount {
+                    expected: 2,
+                    found: 1
+                },
+                ..
+            }]
+        ));
+    }
+
+    fn op(name: &'static str, operands: Vec<Value>, result: Option<Value>) -> Operation {
+        Operation {
+            operands,
+            results: result.into_iter().collect(),
+            ..Operation::new(name)
+        }
+    }
+
+    #[test]
+    fn reject_missing_and_bad_defs() {
+        let mut block = Block::new();
+
+        // copied before pushing, so the def was never filled in
+        let val = Value::new(None);
+        block.push(op("test.val", Vec::new(), Some(val)));
+        block.push(op("test.use", vec![val], None));
+
+        let dangling = Value::new(Some(Ptr::new(42)));
+        block.push(op("test.val", Vec::new(), Some(dangling)));
+
+        let kinds: Vec<_> = verify(&block)
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MissingDef(val),
+                DiagnosticKind::BadDef(dangling)
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_misplaced_terminator() {
+        let src = "\
+.bb0:
+    %0 := test.const {value = 1}
+    test.ret %0
+    %1 := test.neg %0
+";
+        assert_eq!(kinds(src), vec![DiagnosticKind::MisplacedTerminator]);
+    }
+
+    #[test]
+    fn reject_constraint_violations() {
+        let src = "\
+.bb0:
+    %0 := test.const
+    test.neg %0, %0
+    test.func
+";
+        assert_eq!(
+            kinds(src),
+            vec![
+                DiagnosticKind::MissingAttribute("value"),
+                DiagnosticKind::OperandCount {
+                    expected: 1,
+                    found: 2
+                },
+                DiagnosticKind::ResultCount {
+                    expected: 1,
+                    found: 0
+                },
+                DiagnosticKind::BlockCount {
+                    expected: 1,
+                    found: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_wrong_attribute_kind() {
+        let src = "\
+.bb0:
+    %0 := test.const {value = \"five\"}
+    %1 := test.const {value = 5 : u8}
+";
+        assert_eq!(
+            kinds(src),
+            vec![DiagnosticKind::AttributeKind {
+                name: "value",
+                expected: AttrKind::Int,
+                found: AttrKind::Str,
+            }]
+        );
+    }
+
+    #[test]
+    fn reject_type_mismatches() {
+        let verifier = Verifier::new()
+            .constrain(
+                "test.neg",
+                OpConstraint::new(1)
+                    .operand_names(&["val"])
+                    .result()
+                    .ty("val", TypeConstraint::Int)
+                    .ty("result", TypeConstraint::SameAs("val")),
+            )
+            .constrain(
+                "test.load",
+                OpConstraint::new(1)
+                    .operand_names(&["addr"])
+                    .result()
+                    .ty("addr", TypeConstraint::Ptr)
+                    .ty("result", TypeConstraint::Exact("i32")),
+            );
+
+        let src = "\
+.bb0:
+    %0: i32 := test.const {value = 1}
+    %1: i32 := test.neg %0
+    %2: i64 := test.neg %1
+    %3 := test.neg %0
+    %4: ptr<i32> := test.addr
+    %5: i32 := test.load %4
+    %6: i32 := test.load %5
+";
+        let kinds: Vec<_> = verifier
+            .verify(&parse(src).unwrap())
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+
+        let i32 = Type::int(32, true);
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::TypeMismatch {
+                    name: "result",
+                    expected: TypeConstraint::SameAs("val"),
+                    found: Some(Type::int(64, true)),
+                },
+                DiagnosticKind::TypeMismatch {
+                    name: "result",
+                    expected: TypeConstraint::SameAs("val"),
+                    found: None,
+                },
+                DiagnosticKind::TypeMismatch {
+                    name: "addr",
+                    expected: TypeConstraint::Ptr,
+                    found: Some(i32),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn check_named_results() {
+        let verifier = Verifier::new().constrain(
+            "test.divmod",
+            OpConstraint::new(2)
+                .operand_names(&["lhs", "rhs"])
+                .results(2)
+                .result_names(&["quot", "rem"])
+                .ty("rem", TypeConstraint::SameAs("lhs")),
+        );
+
+        let src = "\
+.bb0:
+    %0: i32 := test.const {value = 7}
+    %1: i32, %2: i32 := test.divmod %0, %0
+    %3: i32, %4: u8 := test.divmod %0, %0
+    %5 := test.divmod %0, %0
+";
+        let kinds: Vec<_> = verifier
+            .verify(&parse(src).unwrap())
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::TypeMismatch {
+                    name: "rem",
+                    expected: TypeConstraint::SameAs("lhs"),
+                    found: Some(Type::int(8, false)),
+                },
+                DiagnosticKind::ResultCount {
+                    expected: 2,
+                    found: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_symbols_in_enclosing_tables() {
+        let verifier =
+            Verifier::new().constrain("test.module", OpConstraint::new(0).blocks(1).symbol_table());
+
+        let src = "\
+.bb0:
+    test.func {sym_name = \"f\"} {
+    .bb1:
+        test.call {callee = @g}
+    }
+    test.module {sym_name = \"m\"} {
+    .bb2:
+        test.func {sym_name = \"g\"} {
+        .bb3:
+            test.call {callee = @f}
+            test.call {callee = @g}
+        }
+        test.func {sym_name = \"g\"}
+    }
+    test.func {sym_name = \"f\"}
+    test.call {callee = @m, args = [@h]}
+";
+        let kinds: Vec<_> = verifier
+            .verify(&parse(src).unwrap())
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+
+        // @g is only visible inside the module, @h isn't defined anywhere
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::DuplicateSymbol("f".to_owned()),
+                DiagnosticKind::UndefinedSymbol("g".to_owned()),
+                DiagnosticKind::DuplicateSymbol("g".to_owned()),
+                DiagnosticKind::UndefinedSymbol("h".to_owned()),
+            ]
+        );
+    }
+
+    mod isolated {
+        use crate::{Block, Operation, Value, def_op};
+
+        def_op! {
+            iso.func(body: Block) [IsolatedFromAbove]
+        }
+
+        def_op! {
+            iso.ret(val: Value) -> None [Terminator]
+        }
+
+        def_op! {
+            iso.br() -> None successors 2 [Terminator]
+        }
+    }
+
+    #[test]
+    fn constrain_by_dialect_definitions() {
+        // definitions are named like the ops the constructors build
+        assert_eq!(
+            isolated::func(Block::new()).name,
+            isolated::func::DEFINITION.name
+        );
+        assert_eq!(
+            isolated::ret(Value::new(None)).name,
+            isolated::ret::DEFINITION.name
+        );
+
+        let verifier = Verifier::new().dialect(
+            &Dialect::new("iso")
+                .op(&isolated::func::DEFINITION)
+                .op(&isolated::ret::DEFINITION),
+        );
+
+        let src = "\
+.bb0:
+    %0 := test.const
+    iso.func {
+    .bb1:
+        iso.ret %0
+    }
+    iso.ret %0
+    iso.func
+";
+        let kinds: Vec<_> = verifier
+            .verify(&parse(src).unwrap())
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+
+        // the use inside the isolated func can't see %0
+        assert!(matches!(
+            kinds.as_slice(),
+            [
+                DiagnosticKind::UndefinedValue(_),
+                DiagnosticKind::MisplacedTerminator,
+                DiagnosticKind::BlockCount {
+                    expected: 1,
+                    found: 0
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn reject_unknown_ops_of_known_dialects() {
+        let verifier = Verifier::new().dialect(&Dialect::new("iso").op(&isolated::ret::DEFINITION));
+
+        let src = "\
+.bb0:
+    %0 := test.const
+    iso.return %0
+    iso.ret %0
+";
+        let diagnostics = verifier.verify(&parse(src).unwrap()).unwrap_err();
+
+        // ops of dialects the verifier doesn't know are left alone
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::UnknownOp);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "'iso.return' in .bb0: no such op in its dialect"
+        );
+    }
+
+    #[test]
+    fn constrain_successors_by_definitions() {
+        assert_eq!(isolated::br().name, isolated::br::DEFINITION.name);
+        assert_eq!(isolated::br::DEFINITION.successors, 2);
+        assert_eq!(isolated::ret::DEFINITION.successors, 0);
+
+        let verifier = Verifier::new().dialect(
+            &Dialect::new("iso")
+                .op(&isolated::br::DEFINITION)
+                .op(&isolated::ret::DEFINITION),
+        );
+
+        let src = "\
+.bb0:
+    test.region {
+    .bb1:
+        %0 := test.const
+        iso.br .bb2, .bb3
+    .bb2:
+        iso.br .bb3
+    .bb3:
+        iso.ret %0
+    }
+";
+        let kinds: Vec<_> = verifier
+            .verify(&parse(src).unwrap())
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect();
+
+        assert_eq!(
+            kinds,
+            [DiagnosticKind::SuccessorCount {
+                expected: 2,
+                found: 1
+            }]
+        );
+    }
+}
@@ -0,0 +1,330 @@
+// Visit the ops and blocks nested in a block, at any depth
+
+use crate::link::LinkedList;
+use crate::{Block, Operation};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WalkOrder {
+    /// Visit a node before the ones nested in it
+    PreOrder,
+    /// Visit a node after the ones nested in it
+    PostOrder,
+}
+
+/// What a walk callback wants to happen next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WalkResult {
+    Advance,
+    /// Don't visit anything nested in this node. Only meaningful in pre-order,
+    /// in post-order the nested nodes were already visited and this is the same as `Advance`.
+    Skip,
+    /// Stop the walk
+    Interrupt,
+}
+
+/// Walk `block` and every block nested in it.
+///
+/// Returns `Interrupt` if the callback stopped the walk, `Advance` otherwise.
+pub fn walk_blocks(
+    block: &Block,
+    order: WalkOrder,
+    mut f: impl FnMut(&Block) -> WalkResult,
+) -> WalkResult {
+    blocks(block, order, &mut f)
+}
+
+fn blocks(block: &Block, order: WalkOrder, f: &mut impl FnMut(&Block) -> WalkResult) -> WalkResult {
+    if order == WalkOrder::PreOrder {
+        match f(block) {
+            WalkResult::Advance => (),
+            WalkResult::Skip => return WalkResult::Advance,
+            WalkResult::Interrupt => return WalkResult::Interrupt,
+        }
+    }
+
+    for op in block.iter() {
+        for nested in op.walk_blocks() {
+            if blocks(nested, order, f) == WalkResult::Interrupt {
+                return WalkResult::Interrupt;
+            }
+        }
+    }
+
+    if order == WalkOrder::PostOrder && f(block) == WalkResult::Interrupt {
+        return WalkResult::Interrupt;
+    }
+
+    WalkResult::Advance
+}
+
+/// Walk `block` and every block nested in it, allowing changes.
+///
+/// In pre-order a block's nested blocks are found after the callback has
+/// run on it, so ops it adds are walked into and ops it removes aren't.
+pub fn walk_blocks_mut(
+    block: &mut Block,
+    order: WalkOrder,
+    mut f: impl FnMut(&mut Block) -> WalkResult,
+) -> WalkResult {
+    blocks_mut(block, order, &mut f)
+}
+
+fn blocks_mut(
+    block: &mut Block,
+    order: WalkOrder,
+    f: &mut impl FnMut(&mut Block) -> WalkResult,
+) -> WalkResult {
+    if order == WalkOrder::PreOrder {
+        match f(block) {
+            WalkResult::Advance => (),
+            WalkResult::Skip => return WalkResult::Advance,
+            WalkResult::Interrupt => return WalkResult::Interrupt,
+        }
+    }
+
+    let mut next = *block.head();
+
+    while let Some(ptr) = next {
+        let op = block.get_mut(ptr);
+
+        for nested in op.walk_blocks_mut() {
+            if blocks_mut(nested, order, f) == WalkResult::Interrupt {
+                return WalkResult::Interrupt;
+            }
+        }
+
+        next = op.ahead;
+    }
+
+    if order == WalkOrder::PostOrder && f(block) == WalkResult::Interrupt {
+        return WalkResult::Interrupt;
+    }
+
+    WalkResult::Advance
+}
+
+/// Walk regions in pre-order, lending each one to `f` for all of `'a`.
+///
+/// `f` hands the region back once it's done with it so the regions nested in
+/// its blocks can be walked. This lets `f` keep state tied to `'a`, like a
+/// `RewritingCtx`.
+pub(crate) fn walk_regions_lent<'a>(
+    region: &'a mut [Block],
+    f: &mut impl FnMut(&'a mut [Block]) -> (&'a mut [Block], WalkResult),
+) -> WalkResult {
+    let region = match f(region) {
+        (region, WalkResult::Advance) => region,
+        (_, WalkResult::Skip) => return WalkResult::Advance,
+        (_, WalkResult::Interrupt) => return WalkResult::Interrupt,
+    };
+
+    for block in region {
+        // nested regions have to be borrowed for `'a` too, which rules out following
+        // the links while holding on to the block, so find the list order up front
+        let position = block.list_positions();
+
+        // ops allocated but never linked aren't part of the block
+        let mut ops: Vec<_> = block
+            .pool
+            .entries_mut()
+            .filter_map(|(ptr, op)| Some((position[ptr.idx]?, op)))
+            .collect();
+        ops.sort_by_key(|&(position, _)| position);
+
+        for (_, op) in ops {
+            if !op.blocks.is_empty()
+                && walk_regions_lent(&mut op.blocks, f) == WalkResult::Interrupt
+            {
+                return WalkResult::Interrupt;
+            }
+        }
+    }
+
+    WalkResult::Advance
+}
+
+/// Walk every op nested in `block`, at any depth.
+///
+/// Returns `Interrupt` if the callback stopped the walk, `Advance` otherwise.
+pub fn walk_ops(
+    block: &Block,
+    order: WalkOrder,
+    mut f: impl FnMut(&Operation) -> WalkResult,
+) -> WalkResult {
+    ops(block, order, &mut f)
+}
+
+fn ops(
+    block: &Block,
+    order: WalkOrder,
+    f: &mut impl FnMut(&Operation) -> WalkResult,
+) -> WalkResult {
+    for op in block.iter() {
+        if order == WalkOrder::PreOrder {
+            match f(op) {
+                WalkResult::Advance => (),
+                WalkResult::Skip => continue,
+                WalkResult::Interrupt => return WalkResult::Interrupt,
+            }
+        }
+
+        for nested in op.walk_blocks() {
+            if ops(nested, order, f) == WalkResult::Interrupt {
+                return WalkResult::Interrupt;
+            }
+        }
+
+        if order == WalkOrder::PostOrder && f(op) == WalkResult::Interrupt {
+            return WalkResult::Interrupt;
+        }
+    }
+
+    WalkResult::Advance
+}
+
+/// Walk every op nested in `block`, at any depth, allowing changes to them.
+///
+/// Ops are reached through their block's links, so the callback shouldn't relink them.
+pub fn walk_ops_mut(
+    block: &mut Block,
+    order: WalkOrder,
+    mut f: impl FnMut(&mut Operation) -> WalkResult,
+) -> WalkResult {
+    ops_mut(block, order, &mut f)
+}
+
+fn ops_mut(
+    block: &mut Block,
+    order: WalkOrder,
+    f: &mut impl FnMut(&mut Operation) -> WalkResult,
+) -> WalkResult {
+    let mut next = *block.head();
+
+    while let Some(ptr) = next {
+        let op = block.get_mut(ptr);
+        next = op.ahead;
+
+        if order == WalkOrder::PreOrder {
+            match f(op) {
+                WalkResult::Advance => (),
+                WalkResult::Skip => continue,
+                WalkResult::Interrupt => return WalkResult::Interrupt,
+            }
+        }
+
+        for nested in op.walk_blocks_mut() {
+            if ops_mut(nested, order, f) == WalkResult::Interrupt {
+                return WalkResult::Interrupt;
+            }
+        }
+
+        if order == WalkOrder::PostOrder && f(op) == WalkResult::Interrupt {
+            return WalkResult::Interrupt;
+        }
+    }
+
+    WalkResult::Advance
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse;
+
+    const NESTED: &str = "\
+.bb0:
+    test.a {
+    .bb1:
+        test.b {
+        .bb2:
+            test.c
+        }
+        test.d
+    }
+    test.e {
+    .bb3:
+        test.f
+    }
+";
+
+    fn op_names(order: WalkOrder, skip: &str, stop: &str) -> Vec<&'static str> {
+        let block = parse(NESTED).unwrap();
+        let mut names = Vec::new();
+
+        walk_ops(&block, order, |op| {
+            names.push(op.name);
+
+            match op.name {
+                name if name == skip => WalkResult::Skip,
+                name if name == stop => WalkResult::Interrupt,
+                _ => WalkResult::Advance,
+            }
+        });
+
+        names
+    }
+
+    #[test]
+    fn walk_ops_in_order() {
+        assert_eq!(
+            op_names(WalkOrder::PreOrder, "", ""),
+            vec!["test.a", "test.b", "test.c", "test.d", "test.e", "test.f"]
+        );
+        assert_eq!(
+            op_names(WalkOrder::PostOrder, "", ""),
+            vec!["test.c", "test.b", "test.d", "test.a", "test.f", "test.e"]
+        );
+    }
+
+    #[test]
+    fn skip_and_interrupt() {
+        assert_eq!(
+            op_names(WalkOrder::PreOrder, "test.b", "test.e"),
+            vec!["test.a", "test.b", "test.d", "test.e"]
+        );
+        assert_eq!(
+            op_names(WalkOrder::PostOrder, "test.b", "test.d"),
+            vec!["test.c", "test.b", "test.d"]
+        );
+    }
+
+    #[test]
+    fn walk_blocks_at_any_depth() {
+        let mut block = parse(NESTED).unwrap();
+
+        // the first op of each block names it
+        let first = |block: &Block| block.iter().next().unwrap().name;
+
+        let mut names = Vec::new();
+        walk_blocks(&block, WalkOrder::PreOrder, |block| {
+            names.push(first(block));
+            WalkResult::Advance
+        });
+        assert_eq!(names, vec!["test.a", "test.b", "test.c", "test.f"]);
+
+        names.clear();
+        walk_blocks_mut(&mut block, WalkOrder::PostOrder, |block| {
+            names.push(first(block));
+            WalkResult::Advance
+        });
+        assert_eq!(names, vec!["test.c", "test.b", "test.f", "test.a"]);
+    }
+
+    #[test]
+    fn walk_ops_mut_reaches_nested_ops() {
+        let mut block = parse(NESTED).unwrap();
+
+        walk_ops_mut(&mut block, WalkOrder::PreOrder, |op| {
+            op.name = "test.x";
+            WalkResult::Advance
+        });
+
+        let mut count = 0;
+        walk_ops(&block, WalkOrder::PreOrder, |op| {
+            assert_eq!(op.name, "test.x");
+            count += 1;
+            WalkResult::Advance
+        });
+        assert_eq!(count, 6);
+    }
+}
@@ -15,6 +15,13 @@ rewrite_rule! {
             let (src, dst) = (rewrite(src), rewrite(dst));
             format!("movl   {src},{dst}")
         },
+        Instruction::Unary { op, dst } => {
+            let op = match op {
+                UnaryOperator::Neg => "negl",
+                UnaryOperator::Not => "notl",
+            };
+            format!("{op}   {}", rewrite::<_, String>(dst))
+        },
         Instruction::Ret => "ret".to_owned()
     }
 }
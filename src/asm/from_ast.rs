@@ -4,27 +4,43 @@ use super::nodes as asm;
 use crate::parser::ast;
 
 rewrite_rule! {
-    ast::Expr => asm::Operand {
-        ast::Expr::Constant(value) => asm::Operand::Imm(*value),
-        ast::Expr::Unary(unary_op, expr) => todo!("Unary expr lowering")
+    ast::UnaryOp => asm::UnaryOperator {
+        ast::UnaryOp::Complement => asm::UnaryOperator::Not,
+        ast::UnaryOp::Negate => asm::UnaryOperator::Neg,
     }
 }
 
+// expressions are evaluated into the register
 rewrite_rule! {
-    ast::Stmt => Vec<asm::Instruction> {
-        ast::Stmt::Return(expr) => vec![
-            asm::Instruction::Mov {
-                src: rewrite(expr),
+    ast::Expr => Vec<asm::Instruction> {
+        ast::Node { kind: ast::ExprKind::Constant(value), .. } => vec![asm::Instruction::Mov {
+            src: asm::Operand::Imm(*value),
+            dst: asm::Operand::Register,
+        }],
+        ast::Node { kind: ast::ExprKind::Unary(op, expr), .. } => {
+            let mut ins: Vec<asm::Instruction> = rewrite(expr.as_ref());
+            ins.push(asm::Instruction::Unary {
+                op: rewrite(op),
                 dst: asm::Operand::Register,
-            },
-            asm::Instruction::Ret,
-        ],
+            });
+            ins
+        }
+    }
+}
+
+rewrite_rule! {
+    ast::Stmt => Vec<asm::Instruction> {
+        ast::Node { kind: ast::StmtKind::Return(expr), .. } => {
+            let mut ins: Vec<asm::Instruction> = rewrite(expr);
+            ins.push(asm::Instruction::Ret);
+            ins
+        }
     }
 }
 
 rewrite_rule! {
     ast::Decl => asm::Decl {
-        ast::Decl::Function(name, stmt) => asm::Decl::Function {
+        ast::Node { kind: ast::DeclKind::Function(name, stmt), .. } => asm::Decl::Function {
             name: name.to_string(),
             body: rewrite(stmt.as_ref()),
         },
@@ -2,8 +2,6 @@ mod emit;
 mod from_ast;
 mod nodes;
 
-pub use emit::*;
-pub use from_ast::*;
 pub use nodes::*;
 
 use crate::parser::ast;
@@ -16,3 +14,39 @@ pub fn lower_ast(prg: &ast::Program) -> Program {
 pub fn emit(prg: &Program) -> String {
     rewrite(prg)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, tokenize};
+
+    #[test]
+    fn emit_return_constant() {
+        let tokens = tokenize("int main(void) { return 2; }").unwrap();
+        let program = parse(&mut tokens.into_iter()).unwrap();
+
+        let asm = emit(&lower_ast(&program));
+
+        assert!(asm.contains(".globl main"));
+        assert!(asm.contains("movl   $2,%eax"));
+        assert!(asm.contains("ret"));
+    }
+
+    #[test]
+    fn lower_unary_exprs() {
+        let tokens = tokenize("int main(void) { return -(~2); }").unwrap();
+        let program = parse(&mut tokens.into_iter()).unwrap();
+
+        let asm = emit(&lower_ast(&program));
+
+        let ins: Vec<_> = asm.lines().map(str::trim).collect();
+        let start = ins
+            .iter()
+            .position(|&line| line == "movl   $2,%eax")
+            .unwrap();
+        assert_eq!(
+            ins[start..start + 4],
+            ["movl   $2,%eax", "notl   %eax", "negl   %eax", "ret"]
+        );
+    }
+}
@@ -14,9 +14,16 @@ pub enum Decl {
 #[derive(Debug)]
 pub enum Instruction {
     Mov { src: Operand, dst: Operand },
+    Unary { op: UnaryOperator, dst: Operand },
     Ret,
 }
 
+#[derive(Debug)]
+pub enum UnaryOperator {
+    Neg,
+    Not,
+}
+
 #[derive(Debug)]
 pub enum Operand {
     Imm(u32),
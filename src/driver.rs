@@ -8,8 +8,9 @@ use std::process::Command;
 use crate::error::CompilerError;
 use crate::parser;
 use crate::parser::ast;
+use crate::src::Source;
 use dialect::x86;
-use lorax::rewrite_ops;
+use lorax::{Block, PassManager};
 
 const CC: &str = "gcc";
 
@@ -113,30 +114,44 @@ impl Drop for ProcFile<'_> {
     }
 }
 
-pub fn preprocess(src: ProcFile) -> io::Result<ProcFile> {
+/// Run the C compiler, failing with what it printed if it fails
+fn run_cc(command: &mut Command) -> Result<(), CompilerError> {
+    let output = command.output()?;
+
+    if !output.status.success() {
+        return Err(CompilerError::Cc(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn preprocess(src: ProcFile) -> Result<ProcFile, CompilerError> {
     let mut dst = src.clone();
     dst.kind = ProcFileKind::Preprocessed;
 
-    Command::new(CC)
-        .arg("-E")
-        .arg("-P")
-        .arg(src.get_fn())
-        .arg("-o")
-        .arg(dst.get_fn())
-        .output()?;
+    run_cc(
+        Command::new(CC)
+            .arg("-E")
+            .arg(src.get_fn())
+            .arg("-o")
+            .arg(dst.get_fn()),
+    )?;
 
     Ok(dst)
 }
 
-pub fn assemble(src: ProcFile) -> io::Result<ProcFile> {
+pub fn assemble(src: ProcFile) -> Result<ProcFile, CompilerError> {
     let mut dst = src.clone();
     dst.kind = ProcFileKind::Binary;
 
-    Command::new(CC)
-        .arg(src.get_fn())
-        .arg("-o")
-        .arg(dst.get_fn())
-        .output()?;
+    run_cc(
+        Command::new(CC)
+            .arg(src.get_fn())
+            .arg("-o")
+            .arg(dst.get_fn()),
+    )?;
 
     Ok(dst)
 }
@@ -149,6 +164,17 @@ pub fn parser(tokens: Vec<ast::Token>) -> Result<ast::Program, CompilerError> {
     parser::parse(&mut tokens.into_iter()).map_err(CompilerError::Parser)
 }
 
+/// Check that the IR is well formed, in debug builds only
+fn verify(ir: &Block) -> Result<(), CompilerError> {
+    if cfg!(debug_assertions) {
+        dialect::verifier()
+            .verify(ir)
+            .map_err(CompilerError::Verifier)?;
+    }
+
+    Ok(())
+}
+
 #[derive(clap::Parser)]
 pub struct Cli {
     input: String,
@@ -164,20 +190,83 @@ pub struct Cli {
 
     #[arg(long, action = clap::ArgAction::SetTrue)]
     codegen: bool,
+
+    /// Print where each op came from along with the IR
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    print_locations: bool,
+
+    /// The passes to run on the IR before emitting it, e.g. `func.func(canonicalize),x86-lower`
+    #[arg(long, default_value = "x86-lower")]
+    passes: String,
+
+    /// Print the IR before each run of the pass
+    #[arg(long, value_name = "PASS")]
+    print_ir_before: Vec<String>,
+
+    /// Print the IR after each run of the pass
+    #[arg(long, value_name = "PASS")]
+    print_ir_after: Vec<String>,
+
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    print_ir_before_all: bool,
+
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    print_ir_after_all: bool,
+}
+
+impl Cli {
+    fn print_ir(&self, ir: &Block) {
+        if self.print_locations {
+            println!("{:#}", ir);
+        } else {
+            println!("{}", ir);
+        }
+    }
+
+    /// The pipeline given by `--passes`, dumping IR as asked and verifying between passes in debug builds
+    fn pass_manager(&self) -> Result<PassManager, CompilerError> {
+        let mut pm = PassManager::parse(&self.passes, &dialect::passes())?;
+
+        for pass in &self.print_ir_before {
+            pm = pm.print_ir_before(pass);
+        }
+
+        for pass in &self.print_ir_after {
+            pm = pm.print_ir_after(pass);
+        }
+
+        if self.print_ir_before_all {
+            pm = pm.print_ir_before_all();
+        }
+
+        if self.print_ir_after_all {
+            pm = pm.print_ir_after_all();
+        }
+
+        if self.print_locations {
+            pm = pm.dump_with(|title, ir| eprintln!("// -----// {} //----- //\n{:#}", title, ir));
+        }
+
+        if cfg!(debug_assertions) {
+            pm = pm.verify_each(dialect::verifier());
+        }
+
+        Ok(pm)
+    }
 }
 
 pub fn run_compiler(cli: Cli) -> Result<(), CompilerError> {
-    let input_fn = cli.input;
+    let input_fn = cli.input.clone();
 
     let file = ProcFile::from_fn(&input_fn)
         .ok_or_else(|| CompilerError::Parser("Invalid source file".to_string()))?;
 
     let src_file = preprocess(file)?;
     let asm_file = src_file.to_kind(ProcFileKind::Assembly);
-    let src = src_file.read()?;
+    let src = Source::preprocessed(&src_file.read()?);
 
     // tokenization
-    let tokens = tokenize(&src)?;
+    let tokens = tokenize(&src.text)?;
     if cli.lex {
         dbg!(&tokens);
         return Ok(());
@@ -191,29 +280,25 @@ pub fn run_compiler(cli: Cli) -> Result<(), CompilerError> {
     }
 
     // 'tacky' is the option to generate IR
-    let ir = &mut parser::lower_program(&ast);
+    dialect::register();
+    let ir = &mut parser::lower_program(&ast, &src, &input_fn);
+    verify(ir)?;
+
     if cli.tacky {
-        println!("{}", ir);
+        cli.print_ir(ir);
         return Ok(());
     }
 
     // codegen
-
-    // TODO: put this somewhere else
-
-    rewrite_ops(ir, x86::rules());
-
-    println!("{}", ir);
+    cli.pass_manager()?.run(ir)?;
 
     if cli.codegen {
-        println!("{}", ir);
+        cli.print_ir(ir);
         return Ok(());
     }
 
-    todo!()
-
-    // asm_file.write(asm)?;
-    // assemble(asm_file)?;
+    asm_file.write(x86::emit(ir)?)?;
+    assemble(asm_file)?;
 
-    // Ok(())
+    Ok(())
 }
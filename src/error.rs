@@ -1,13 +1,23 @@
 use std::fmt::{self};
 use std::process::Termination;
 
+use dialect::x86::EmitError;
+use lorax::verify::Diagnostic;
+use lorax::{PassError, PipelineError};
+
 use crate::parser::ast::{Token, TokenKind};
 use crate::src::Source;
 
 pub enum CompilerError {
     IO(std::io::Error),
+    /// The C compiler failed, with what it printed
+    Cc(String),
     Parser(String),
     Lexer(Source, Token),
+    Emit(EmitError),
+    Verifier(Vec<Diagnostic>),
+    Pipeline(PipelineError),
+    Pass(PassError),
 }
 
 impl From<std::io::Error> for CompilerError {
@@ -16,10 +26,29 @@ impl From<std::io::Error> for CompilerError {
     }
 }
 
+impl From<EmitError> for CompilerError {
+    fn from(error: EmitError) -> Self {
+        CompilerError::Emit(error)
+    }
+}
+
+impl From<PipelineError> for CompilerError {
+    fn from(error: PipelineError) -> Self {
+        CompilerError::Pipeline(error)
+    }
+}
+
+impl From<PassError> for CompilerError {
+    fn from(error: PassError) -> Self {
+        CompilerError::Pass(error)
+    }
+}
+
 impl fmt::Display for CompilerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CompilerError::IO(e) => write!(f, "I/O error: {}", e),
+            CompilerError::Cc(stderr) => write!(f, "C compiler failed:\n{}", stderr.trim_end()),
             CompilerError::Parser(msg) => write!(f, "Parse error: {}", msg),
             CompilerError::Lexer(src, tok) => {
                 write!(
@@ -32,6 +61,18 @@ impl fmt::Display for CompilerError {
                     }
                 )
             }
+            CompilerError::Emit(e) => write!(f, "Codegen error: {}", e),
+            CompilerError::Verifier(diagnostics) => {
+                write!(f, "Invalid IR:")?;
+
+                for diagnostic in diagnostics {
+                    write!(f, "\n    {}", diagnostic)?;
+                }
+
+                Ok(())
+            }
+            CompilerError::Pipeline(e) => write!(f, "Invalid pipeline: {}", e),
+            CompilerError::Pass(e) => write!(f, "Pass error: {}", e),
         }
     }
 }
@@ -1,3 +1,4 @@
+pub mod asm;
 pub mod driver;
 pub mod error;
 pub mod parser;
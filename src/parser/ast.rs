@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 // tokens
 #[derive(Debug, PartialEq)]
 pub enum TokenKind {
@@ -34,25 +36,45 @@ pub struct Token {
     pub offset: usize,
 }
 
+impl Token {
+    /// The bytes of the source the token was read from
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.value.len()
+    }
+}
+
 // ast nodes
 
+/// A node, and the bytes of the source it's named by in diagnostics
+#[derive(Debug)]
+pub struct Node<K> {
+    pub kind: K,
+    pub span: Range<usize>,
+}
+
 #[derive(Debug)]
 pub struct Program {
     pub body: Decl,
 }
 
+pub type Decl = Node<DeclKind>;
+
 #[derive(Debug)]
-pub enum Decl {
+pub enum DeclKind {
     Function(String, Box<Stmt>),
 }
 
+pub type Stmt = Node<StmtKind>;
+
 #[derive(Debug)]
-pub enum Stmt {
+pub enum StmtKind {
     Return(Expr),
 }
 
+pub type Expr = Node<ExprKind>;
+
 #[derive(Debug)]
-pub enum Expr {
+pub enum ExprKind {
     Constant(u32),
     Unary(UnaryOp, Box<Expr>),
 }
@@ -1,45 +1,72 @@
 // Lower AST to IR
 
-use lorax::{Block, Value};
+use std::ops::Range;
+
+use lorax::attr::TypedInt;
+use lorax::{Block, Location, OpBuilder, Type, Value};
 
 use super::ast;
+use crate::src::Source;
 
 use dialect::{
     arith,
     func::{func, ret},
 };
 
-fn lower_expr(block: &mut Block, expr: &ast::Expr) -> Value {
-    let op = match expr {
-        ast::Expr::Unary(unary_op, expr) => match unary_op {
-            ast::UnaryOp::Complement => todo!(),
-            ast::UnaryOp::Negate => arith::negate(lower_expr(block, expr)),
+/// Where the lowered nodes came from
+struct Origin<'a> {
+    file: &'a str,
+    src: &'a Source,
+}
+
+impl Origin<'_> {
+    fn location(&self, span: &Range<usize>) -> Location {
+        let file = self.src.file_of(span.start).unwrap_or(self.file);
+
+        match self.src.line_col(span.start) {
+            Some((line, col)) => Location::span(file, line, col, span.len()),
+            None => Location::Unknown,
+        }
+    }
+}
+
+fn lower_expr(origin: &Origin, builder: &mut OpBuilder, expr: &ast::Expr) -> Value {
+    let op = match &expr.kind {
+        ast::ExprKind::Unary(unary_op, inner) => match unary_op {
+            ast::UnaryOp::Complement => arith::complement(lower_expr(origin, builder, inner)),
+            ast::UnaryOp::Negate => arith::negate(lower_expr(origin, builder, inner)),
         },
 
-        ast::Expr::Constant(val) => arith::constant(*val),
+        ast::ExprKind::Constant(val) => {
+            arith::constant(TypedInt((*val).into(), Type::int(32, true)))
+        }
     };
 
-    let ptr = block.push(op);
-    block.get(ptr).get_result()
+    builder.build(op.at(origin.location(&expr.span)))
 }
 
-pub fn lower_stmt(block: &mut Block, stmt: &ast::Stmt) {
-    let op = match stmt {
-        ast::Stmt::Return(expr) => ret(lower_expr(block, expr)),
+fn lower_stmt(origin: &Origin, builder: &mut OpBuilder, stmt: &ast::Stmt) {
+    let op = match &stmt.kind {
+        ast::StmtKind::Return(expr) => ret(lower_expr(origin, builder, expr)),
     };
 
-    block.push(op);
+    builder.insert(op.at(origin.location(&stmt.span)));
 }
 
-pub fn lower_program(program: &ast::Program) -> Block {
+/// Lower the program read from `src`, which is the file named `file` unless
+/// its linemarkers say otherwise
+pub fn lower_program(program: &ast::Program, src: &Source, file: &str) -> Block {
+    let origin = Origin { file, src };
     let mut region = Block::new();
 
-    match &program.body {
-        ast::Decl::Function(_, stmt) => {
+    let decl = &program.body;
+    match &decl.kind {
+        ast::DeclKind::Function(name, stmt) => {
             let mut block = Block::new();
 
-            lower_stmt(&mut block, stmt);
-            region.push(func(block));
+            lower_stmt(&origin, &mut OpBuilder::at_end(&mut block), stmt);
+            OpBuilder::at_end(&mut region)
+                .insert(func(block, name.clone()).at(origin.location(&decl.span)));
         }
     };
 
@@ -36,10 +36,13 @@ impl<I: iter::Iterator<Item = Token>> Parser<'_, I> {
     }
 
     fn parse_statement(&mut self) -> ParseResult<Stmt> {
-        self.expect(TokenKind::Return)?;
+        let keyword = self.expect(TokenKind::Return)?;
         let return_val: Result<Expr, String> = self.parse_expr();
         self.expect(TokenKind::Semicolon)?;
-        Ok(Stmt::Return(return_val?))
+        Ok(Stmt {
+            kind: StmtKind::Return(return_val?),
+            span: keyword.span(),
+        })
     }
 
     fn parse_unaryop(&mut self) -> ParseResult<UnaryOp> {
@@ -54,12 +57,19 @@ impl<I: iter::Iterator<Item = Token>> Parser<'_, I> {
         let expr = match self.peek()?.kind {
             TokenKind::Constant => {
                 let token = self.expect(TokenKind::Constant)?;
-                Expr::Constant(token.value.parse().unwrap())
+                Expr {
+                    kind: ExprKind::Constant(token.value.parse().unwrap()),
+                    span: token.span(),
+                }
             }
             TokenKind::Negate | TokenKind::Complement => {
+                let span = self.peek()?.span();
                 let op = self.parse_unaryop()?;
                 let inner_expr = self.parse_expr()?;
-                Expr::Unary(op, Box::new(inner_expr))
+                Expr {
+                    kind: ExprKind::Unary(op, Box::new(inner_expr)),
+                    span,
+                }
             }
             TokenKind::LParen => {
                 self.expect(TokenKind::LParen)?;
@@ -85,7 +95,10 @@ impl<I: iter::Iterator<Item = Token>> Parser<'_, I> {
         let body = self.parse_statement()?;
         self.expect(TokenKind::RBrace)?;
 
-        Ok(Decl::Function(name.value, Box::new(body)))
+        Ok(Decl {
+            span: name.span(),
+            kind: DeclKind::Function(name.value, Box::new(body)),
+        })
     }
 
     fn parse_program(&mut self) -> ParseResult<Program> {
@@ -136,8 +149,8 @@ mod tests {
             }
             .parse_expr()
             .unwrap();
-            match expr {
-                Expr::Constant(v) => prop_assert_eq!(v, val),
+            match expr.kind {
+                ExprKind::Constant(v) => prop_assert_eq!(v, val),
                 _ => prop_assert!(false, "Expected constant expr"),
             }
         }
@@ -154,9 +167,9 @@ mod tests {
             }
             .parse_expr()
             .unwrap();
-            match expr {
-                Expr::Unary(UnaryOp::Negate, inner) => match *inner {
-                    Expr::Constant(v) => prop_assert_eq!(v, val),
+            match expr.kind {
+                ExprKind::Unary(UnaryOp::Negate, inner) => match inner.kind {
+                    ExprKind::Constant(v) => prop_assert_eq!(v, val),
                     _ => prop_assert!(false, "Expected constant inside unary"),
                 },
                 _ => prop_assert!(false, "Expected unary expr"),
@@ -176,8 +189,8 @@ mod tests {
             }
             .parse_expr()
             .unwrap();
-            match expr {
-                Expr::Constant(v) => prop_assert_eq!(v, val),
+            match expr.kind {
+                ExprKind::Constant(v) => prop_assert_eq!(v, val),
                 _ => prop_assert!(false, "Expected constant expr in parens"),
             }
         }
@@ -195,8 +208,8 @@ mod tests {
             }
             .parse_statement()
             .unwrap();
-            match stmt {
-                Stmt::Return(Expr::Constant(v)) => prop_assert_eq!(v, val),
+            match stmt.kind {
+                StmtKind::Return(Expr { kind: ExprKind::Constant(v), .. }) => prop_assert_eq!(v, val),
                 _ => prop_assert!(false, "Expected return statement with constant"),
             }
         }
@@ -218,11 +231,14 @@ mod tests {
         ];
         let mut iter = tokens.into_iter().peekable();
         let decl = Parser { tokens: &mut iter }.parse_function().unwrap();
-        match decl {
-            Decl::Function(name, body) => {
+        match decl.kind {
+            DeclKind::Function(name, body) => {
                 assert_eq!(name, "main");
-                match *body {
-                    Stmt::Return(Expr::Constant(val)) => assert_eq!(val, 0),
+                match body.kind {
+                    StmtKind::Return(Expr {
+                        kind: ExprKind::Constant(val),
+                        ..
+                    }) => assert_eq!(val, 0),
                     _ => panic!("Expected return statement in function body"),
                 }
             }
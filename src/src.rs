@@ -1,21 +1,82 @@
 use std::fmt;
+use std::rc::Rc;
 
 use crate::parser::ast::Token;
 
 #[derive(Debug)]
 pub struct Source {
     pub text: String,
+
+    // where each line came from, if the text is preprocessor output with
+    // linemarkers
+    origins: Vec<Option<LineOrigin>>,
+}
+
+#[derive(Debug, Clone)]
+struct LineOrigin {
+    file: Rc<str>,
+    line: usize,
 }
 
 impl Source {
-    pub fn get_span(&self, tok: &Token) -> Option<Span> {
+    pub fn get_span(&self, tok: &Token) -> Option<Span<'_>> {
         self.pos_of(tok.offset).map(|pos| Span {
             pos,
             len: tok.value.len(),
         })
     }
 
-    fn pos_of(&self, offset: usize) -> Option<Position> {
+    /// Read the output of the preprocessor, run without `-P`. Its linemarkers
+    /// are blanked out of the text, and say where the other lines came from.
+    pub fn preprocessed(text: &str) -> Self {
+        let mut src = Source::from("");
+        let mut next = None;
+
+        for line in text.lines() {
+            match parse_linemarker(line) {
+                Some((line, file)) => {
+                    next = Some(LineOrigin {
+                        file: file.into(),
+                        line,
+                    });
+                    src.origins.push(None);
+                }
+                None => {
+                    src.text.push_str(line);
+                    src.origins.push(next.clone());
+                    if let Some(next) = &mut next {
+                        next.line += 1;
+                    }
+                }
+            }
+
+            src.text.push('\n');
+        }
+
+        src
+    }
+
+    /// The line and column of a byte offset, both counted from 1. For
+    /// preprocessor output, that's the line in the file it came from.
+    pub fn line_col(&self, offset: usize) -> Option<(usize, usize)> {
+        let pos = self.pos_of(offset)?;
+
+        if self.origins.is_empty() {
+            return Some((pos.line + 1, pos.col + 1));
+        }
+
+        let origin = self.origins.get(pos.line)?.as_ref()?;
+        Some((origin.line, pos.col + 1))
+    }
+
+    /// The file a byte offset of preprocessor output came from
+    pub fn file_of(&self, offset: usize) -> Option<&str> {
+        let pos = self.pos_of(offset)?;
+        let origin = self.origins.get(pos.line)?.as_ref()?;
+        Some(&origin.file)
+    }
+
+    fn pos_of(&self, offset: usize) -> Option<Position<'_>> {
         get_pos(self, offset)
     }
 }
@@ -24,6 +85,7 @@ impl From<&str> for Source {
     fn from(src: &str) -> Self {
         Source {
             text: src.to_owned(),
+            origins: Vec::new(),
         }
     }
 }
@@ -56,7 +118,25 @@ impl fmt::Display for Span<'_> {
     }
 }
 
-fn get_pos(src: &Source, offset: usize) -> Option<Position> {
+/// The line number and file name a linemarker like `# 11 "loc.c" 2` gives
+/// the line after it
+fn parse_linemarker(line: &str) -> Option<(usize, String)> {
+    let (line, rest) = line.strip_prefix("# ")?.split_once(' ')?;
+    let mut chars = rest.strip_prefix('"')?.chars();
+    let mut file = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some((line.parse().ok()?, file)),
+            '\\' => file.push(chars.next()?),
+            c => file.push(c),
+        }
+    }
+
+    None
+}
+
+fn get_pos(src: &Source, offset: usize) -> Option<Position<'_>> {
     if offset >= src.text.len() {
         return None;
     }
@@ -1,5 +1,10 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use clap::Parser;
 use proptest::prelude::*;
-use test_each_file::test_each_file;
+use test_each_file::{test_each_file, test_each_path};
 
 use sillydrageon::driver;
 
@@ -20,6 +25,63 @@ fn test_parse_invalid(program: &str) {
     }
 }
 
+test_each_path! { in "tests/valid/" as compile => test_compile_valid }
+fn test_compile_valid(path: &Path) {
+    let name = path.file_stem().unwrap().to_str().unwrap();
+    let dir = std::env::temp_dir().join(format!("sillydrageon-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let src = dir.join(format!("{name}.c"));
+    fs::copy(path, &src).unwrap();
+
+    // compile with gcc for a reference exit status
+    let reference = dir.join("reference");
+    let status = Command::new("gcc")
+        .arg(&src)
+        .arg("-o")
+        .arg(&reference)
+        .status()
+        .unwrap();
+    assert!(status.success(), "gcc failed to compile {name}");
+
+    let cli = driver::Cli::parse_from(["sillydrageon", src.to_str().unwrap()]);
+    driver::run_compiler(cli).expect(FAIL_VALID);
+
+    let expected = Command::new(&reference).status().unwrap();
+    let actual = Command::new(dir.join(name)).status().unwrap();
+    assert_eq!(actual.code(), expected.code());
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn locations_point_into_the_source() {
+    let dir = std::env::temp_dir().join(format!("sillydrageon-{}-loc", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    // the preprocessor strips the comment, the define and the blank lines
+    let src = dir.join("loc.c");
+    fs::write(
+        &src,
+        "/* a comment\n   over lines */\n#define TWO 2\n\n\n\n\n\nint main(void)\n{\n    return TWO;\n}\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sillydrageon"))
+        .arg(&src)
+        .arg("--tacky")
+        .arg("--print-locations")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let ir = String::from_utf8(output.stdout).unwrap();
+    let ret = ir.lines().find(|line| line.contains("func.ret")).unwrap();
+    assert!(ret.ends_with(":11:5 to 11:11)"), "{ret}");
+
+    fs::remove_dir_all(&dir).ok();
+}
+
 proptest! {
     #[test]
     fn doesnt_crash(s in any::<String>()) {